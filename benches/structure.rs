@@ -0,0 +1,124 @@
+use std::hint::black_box;
+use std::mem;
+use std::time::Duration;
+use std::time::Instant;
+
+use voxels::Channel;
+
+#[path = "../src/palette.rs"]
+#[allow(dead_code)]
+mod palette;
+
+use palette::PalettedChannel;
+
+const AXIS: usize = 32;
+const COUNT: usize = AXIS * AXIS * AXIS;
+const ITERATIONS: u32 = 20;
+
+const AIR: u64 = 1;
+const STONE: u64 = 2;
+const GRASS: u64 = 3;
+
+fn terrain(height: impl Fn(usize, usize) -> usize) -> Vec<u64> {
+    let mut blocks = vec![AIR; COUNT];
+    for z in 0..AXIS {
+        for x in 0..AXIS {
+            let surface = height(x, z).min(AXIS);
+            for y in 0..surface {
+                blocks[(z * AXIS + y) * AXIS + x] = if y + 1 == surface { GRASS } else { STONE };
+            }
+        }
+    }
+    blocks
+}
+
+fn scenarios() -> Vec<(&'static str, Vec<u64>)> {
+    vec![
+        ("air", vec![AIR; COUNT]),
+        ("solid", vec![GRASS; COUNT]),
+        ("flat", terrain(|_, _| AXIS / 2)),
+        (
+            "hills",
+            terrain(|x, z| {
+                let (x, z) = (x as f32 / 5.0, z as f32 / 7.0);
+                (16.0 + 8.0 * x.sin() * z.cos()) as usize
+            }),
+        ),
+        (
+            "noisy",
+            (0..COUNT)
+                .map(|i| (i as u64).wrapping_mul(0x9e3779b97f4a7c15) >> 61)
+                .collect(),
+        ),
+    ]
+}
+
+fn time(f: impl Fn()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn channel(blocks: &[u64]) -> Channel {
+    let mut channel = Channel::default();
+    channel.extend(blocks.iter().copied());
+    channel
+}
+
+fn paletted(blocks: &[u64]) -> PalettedChannel {
+    let mut channel = PalettedChannel::new(COUNT, AIR);
+    channel.set_many(blocks.iter().copied().enumerate());
+    channel.compact();
+    channel
+}
+
+fn main() {
+    // The old layout keeps blocks, cull faces and ao in three dense channels.
+    let dense_memory = 3 * COUNT * mem::size_of::<u64>();
+
+    println!(
+        "{:<8} {:>12} {:>12} {:>5} {:>12} {:>12} {:>12} {:>12}",
+        "scenario", "dense B", "palette B", "bits", "dense get", "pal get", "dense set", "pal set"
+    );
+    for (name, blocks) in scenarios() {
+        let dense = channel(&blocks);
+        let compressed = paletted(&blocks);
+        let edits = (0..COUNT as u64)
+            .step_by(7)
+            .map(|i| (i, STONE))
+            .collect::<Vec<_>>();
+
+        let dense_get = time(|| {
+            black_box(dense.get(0..COUNT as u64).into_iter().sum::<u64>());
+        });
+        let paletted_get = time(|| {
+            black_box((0..COUNT).map(|i| compressed.get(i)).sum::<u64>());
+        });
+        // Both set timings include building the channel from scratch.
+        let dense_set = time(|| {
+            let mut dense = channel(&blocks);
+            dense.set(edits.clone());
+            black_box(dense);
+        });
+        let paletted_set = time(|| {
+            let mut compressed = paletted(&blocks);
+            compressed.set_many(edits.iter().map(|&(i, block)| (i as usize, block)));
+            compressed.compact();
+            black_box(compressed);
+        });
+
+        println!(
+            "{:<8} {:>12} {:>12} {:>5} {:>12?} {:>12?} {:>12?} {:>12?}",
+            name,
+            dense_memory,
+            compressed.memory(),
+            compressed.bits_per_entry(),
+            dense_get,
+            paletted_get,
+            dense_set,
+            paletted_set,
+        );
+    }
+}
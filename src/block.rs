@@ -0,0 +1,120 @@
+use bevy::prelude::*;
+
+use crate::Direction;
+
+#[repr(u64)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Block {
+    Void,
+    Air,
+    Stone,
+    Grass,
+    Police,
+    Fire,
+    Health,
+    School,
+    Crosswalk,
+    Asphalt,
+    Centerline,
+    LaneMarking,
+    Curb,
+    Sidewalk,
+    Ballast,
+    Sleeper,
+    Rail,
+}
+
+impl Block {
+    /// Every block, indexed by its id.
+    pub const ALL: [Block; 17] = [
+        Block::Void,
+        Block::Air,
+        Block::Stone,
+        Block::Grass,
+        Block::Police,
+        Block::Fire,
+        Block::Health,
+        Block::School,
+        Block::Crosswalk,
+        Block::Asphalt,
+        Block::Centerline,
+        Block::LaneMarking,
+        Block::Curb,
+        Block::Sidewalk,
+        Block::Ballast,
+        Block::Sleeper,
+        Block::Rail,
+    ];
+
+    /// Blocks the block tool cycles through: every block but `Void` and `Air`.
+    pub const PALETTE: [Block; 15] = [
+        Block::Stone,
+        Block::Grass,
+        Block::Police,
+        Block::Fire,
+        Block::Health,
+        Block::School,
+        Block::Crosswalk,
+        Block::Asphalt,
+        Block::Centerline,
+        Block::LaneMarking,
+        Block::Curb,
+        Block::Sidewalk,
+        Block::Ballast,
+        Block::Sleeper,
+        Block::Rail,
+    ];
+
+    pub fn color(self) -> Vec4 {
+        match self {
+            Block::Void => Vec4::new(1.0, 0.0, 1.0, 1.0),
+            Block::Stone => Vec4::new(0.4, 0.4, 0.4, 1.0),
+            Block::Grass => Vec4::new(0.0, 0.6, 0.09, 1.0),
+            Block::Police => Vec4::new(0.15, 0.25, 0.7, 1.0),
+            Block::Fire => Vec4::new(0.75, 0.12, 0.1, 1.0),
+            Block::Health => Vec4::new(0.9, 0.9, 0.9, 1.0),
+            Block::School => Vec4::new(0.85, 0.65, 0.2, 1.0),
+            Block::Crosswalk => Vec4::new(0.95, 0.95, 0.9, 1.0),
+            Block::Asphalt => Vec4::new(0.12, 0.12, 0.13, 1.0),
+            Block::Centerline => Vec4::new(0.95, 0.75, 0.1, 1.0),
+            Block::LaneMarking => Vec4::new(0.9, 0.9, 0.88, 1.0),
+            Block::Curb => Vec4::new(0.6, 0.6, 0.58, 1.0),
+            Block::Sidewalk => Vec4::new(0.7, 0.68, 0.64, 1.0),
+            Block::Ballast => Vec4::new(0.45, 0.42, 0.38, 1.0),
+            Block::Sleeper => Vec4::new(0.35, 0.22, 0.12, 1.0),
+            Block::Rail => Vec4::new(0.55, 0.55, 0.6, 1.0),
+            _ => Vec4::splat(0.0),
+        }
+    }
+
+    pub fn is_building(self) -> bool {
+        matches!(
+            self,
+            Block::Police | Block::Fire | Block::Health | Block::School
+        )
+    }
+
+    /// Blocks vehicles drive on, which join the road network when placed.
+    pub fn is_road_surface(self) -> bool {
+        matches!(
+            self,
+            Block::Asphalt | Block::Centerline | Block::LaneMarking | Block::Crosswalk
+        )
+    }
+
+    pub fn is_track(self) -> bool {
+        matches!(self, Block::Ballast | Block::Sleeper | Block::Rail)
+    }
+
+    pub fn emission(self, position: UVec3) -> [f32; 6] {
+        let mut emission = [0.0; 6];
+        if !self.is_building() || position.y % 2 == 0 {
+            return emission;
+        }
+        let lit = ((position.x + position.z) % 2 == 0) as i32 as f32;
+        for direction in [Direction::LEFT, Direction::RIGHT, Direction::BACK, Direction::FORWARD] {
+            emission[direction.bits().trailing_zeros() as usize] = lit;
+        }
+        emission
+    }
+}
@@ -0,0 +1,212 @@
+use bevy::prelude::*;
+
+use crate::road::draw_line;
+use crate::road::LineMode;
+use crate::Block;
+use crate::CursorHit;
+use crate::WorldEdit;
+
+/// Largest box side or sphere radius a single fill may cover.
+pub const MAX_FILL: i32 = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum BlockShape {
+    #[default]
+    Single,
+    Line,
+    Box,
+    Sphere,
+}
+
+impl BlockShape {
+    const ALL: [BlockShape; 4] = [
+        BlockShape::Single,
+        BlockShape::Line,
+        BlockShape::Box,
+        BlockShape::Sphere,
+    ];
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&shape| shape == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// Size of the fill checked against [`MAX_FILL`]: the longest box side or
+    /// the sphere radius. Single blocks and lines are never limited.
+    pub fn extent(self, start: IVec3, end: IVec3) -> i32 {
+        match self {
+            BlockShape::Single | BlockShape::Line => 0,
+            BlockShape::Box => (end - start).abs().max_element() + 1,
+            BlockShape::Sphere => (end - start).as_vec3().length().ceil() as i32,
+        }
+    }
+
+    pub fn fill(self, start: IVec3, end: IVec3, mut f: impl FnMut(IVec3)) {
+        match self {
+            BlockShape::Single => (f)(end),
+            BlockShape::Line => draw_line(start, end, LineMode::BOTH, f),
+            BlockShape::Box => {
+                let min = start.min(end);
+                let max = start.max(end);
+                for x in min.x..=max.x {
+                    for y in min.y..=max.y {
+                        for z in min.z..=max.z {
+                            (f)(IVec3::new(x, y, z));
+                        }
+                    }
+                }
+            }
+            BlockShape::Sphere => {
+                let radius = (end - start).as_vec3().length();
+                let extent = radius.ceil() as i32;
+                for x in -extent..=extent {
+                    for y in -extent..=extent {
+                        for z in -extent..=extent {
+                            let offset = IVec3::new(x, y, z);
+                            if offset.as_vec3().length() <= radius + 0.5 {
+                                (f)(start + offset);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct BlockTool {
+    pub enabled: bool,
+    pub block: Block,
+    pub shape: BlockShape,
+    anchor: Option<(IVec3, Block)>,
+}
+
+impl Default for BlockTool {
+    fn default() -> Self {
+        BlockTool {
+            enabled: false,
+            block: Block::Stone,
+            shape: BlockShape::Single,
+            anchor: None,
+        }
+    }
+}
+
+pub fn block_tool_input(keys: Res<Input<KeyCode>>, mut tool: ResMut<BlockTool>) {
+    if keys.just_pressed(KeyCode::B) {
+        tool.enabled = !tool.enabled;
+        tool.anchor = None;
+    }
+    if !tool.enabled {
+        return;
+    }
+    if keys.just_pressed(KeyCode::Tab) {
+        tool.shape = tool.shape.next();
+        tool.anchor = None;
+    }
+    let cycle = keys.just_pressed(KeyCode::BracketRight) as i32
+        - keys.just_pressed(KeyCode::BracketLeft) as i32;
+    if cycle != 0 {
+        let index = Block::PALETTE
+            .iter()
+            .position(|&block| block == tool.block)
+            .unwrap_or(0) as i32;
+        let len = Block::PALETTE.len() as i32;
+        tool.block = Block::PALETTE[(index + cycle).rem_euclid(len) as usize];
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        tool.anchor = None;
+    }
+}
+
+pub fn apply_block_tool(bevy_world: &mut bevy::prelude::World) -> bool {
+    if !bevy_world.resource::<BlockTool>().enabled {
+        return false;
+    }
+    let mouse = bevy_world.resource::<Input<MouseButton>>();
+    let place = mouse.just_pressed(MouseButton::Left);
+    let remove = mouse.just_pressed(MouseButton::Right);
+    if !place && !remove {
+        return true;
+    }
+    let Some(hit) = bevy_world.resource::<CursorHit>().0 else {
+        return true;
+    };
+
+    let tool = bevy_world.resource::<BlockTool>();
+    let (target, block) = if place {
+        (hit.adjacent(), tool.block)
+    } else {
+        (hit.voxel, Block::Air)
+    };
+    let shape = tool.shape;
+
+    let start = match (shape, tool.anchor) {
+        (BlockShape::Single, _) => target,
+        (_, Some((anchor, anchor_block))) if anchor_block == block => anchor,
+        _ => {
+            bevy_world.resource_mut::<BlockTool>().anchor = Some((target, block));
+            return true;
+        }
+    };
+    bevy_world.resource_mut::<BlockTool>().anchor = None;
+
+    let extent = shape.extent(start, target);
+    if extent > MAX_FILL {
+        warn!("{shape:?} fill not applied: size {extent} exceeds {MAX_FILL}");
+        return true;
+    }
+
+    let mut edit = WorldEdit::new();
+    shape.fill(start, target, |position| {
+        edit.set(position, block);
+    });
+    edit.apply(bevy_world);
+    true
+}
+
+pub fn block_tool_gizmo(mut gizmos: Gizmos, cursor: Res<CursorHit>, tool: Res<BlockTool>) {
+    if !tool.enabled {
+        return;
+    }
+    let Some(hit) = cursor.0 else {
+        return;
+    };
+    let voxel_outline = |voxel: IVec3| {
+        Transform::from_translation(voxel.as_vec3() + 0.5).with_scale(Vec3::splat(1.02))
+    };
+    gizmos.cuboid(voxel_outline(hit.voxel), Color::WHITE);
+    gizmos.cuboid(voxel_outline(hit.adjacent()), Color::rgba(1.0, 1.0, 1.0, 0.3));
+
+    if let Some((anchor, block)) = tool.anchor {
+        let target = if block == Block::Air {
+            hit.voxel
+        } else {
+            hit.adjacent()
+        };
+        let color = if block == Block::Air {
+            Color::RED
+        } else {
+            Color::YELLOW
+        };
+        match tool.shape {
+            BlockShape::Single => {}
+            BlockShape::Line => {
+                gizmos.line(anchor.as_vec3() + 0.5, target.as_vec3() + 0.5, color);
+            }
+            BlockShape::Box => {
+                let min = anchor.min(target).as_vec3();
+                let max = anchor.max(target).as_vec3() + 1.0;
+                gizmos.cuboid(
+                    Transform::from_translation((min + max) / 2.0).with_scale(max - min),
+                    color,
+                );
+            }
+            BlockShape::Sphere => {
+                let radius = (target - anchor).as_vec3().length() + 0.5;
+                gizmos.sphere(anchor.as_vec3() + 0.5, Quat::IDENTITY, radius, color);
+            }
+        }
+    }
+}
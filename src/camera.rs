@@ -0,0 +1,226 @@
+use std::f32::consts::PI;
+use std::f32::consts::TAU;
+
+use bevy::ecs::system::SystemState;
+use bevy::input::mouse::MouseMotion;
+use bevy::input::mouse::MouseScrollUnit;
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::column;
+
+const MIN_DISTANCE: f32 = 30.0;
+pub(crate) const MAX_DISTANCE: f32 = 600.0;
+const MIN_PITCH: f32 = PI / 18.0;
+const MAX_PITCH: f32 = PI * 0.47;
+const ZOOM_STEP: f32 = 0.08;
+const ORBIT_SENSITIVITY: f32 = 0.005;
+const PAN_SPEED: f32 = 1.2;
+const EDGE_MARGIN: f32 = 8.0;
+const DAMPING: f32 = 10.0;
+
+/// Spawns the RTS camera rig and drives it from mouse and keyboard input.
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraBookmarks>()
+            .add_systems(Startup, spawn_camera)
+            .add_systems(Update, (camera_input, follow_ground, camera).chain());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CameraState {
+    pub focus: Vec3,
+    pub yaw: f32,
+    pub pitch_offset: f32,
+    pub zoom: f32,
+}
+
+impl CameraState {
+    pub fn distance(&self) -> f32 {
+        MIN_DISTANCE + (MAX_DISTANCE - MIN_DISTANCE) * self.zoom * self.zoom
+    }
+
+    pub fn pitch(&self) -> f32 {
+        let base = PI / 7.0 + (PI / 3.0 - PI / 7.0) * self.zoom;
+        (base + self.pitch_offset).clamp(MIN_PITCH, MAX_PITCH)
+    }
+
+    fn damp(&mut self, target: &CameraState, factor: f32) {
+        self.focus = self.focus.lerp(target.focus, factor);
+        self.yaw += (target.yaw - self.yaw) * factor;
+        self.pitch_offset += (target.pitch_offset - self.pitch_offset) * factor;
+        self.zoom += (target.zoom - self.zoom) * factor;
+    }
+}
+
+#[derive(Component)]
+pub struct RtsCamera {
+    pub current: CameraState,
+    pub target: CameraState,
+    ground_column: Option<IVec2>,
+}
+
+impl RtsCamera {
+    pub fn new(focus: Vec3) -> Self {
+        let state = CameraState {
+            focus,
+            yaw: 0.0,
+            pitch_offset: 0.0,
+            zoom: 0.6,
+        };
+        RtsCamera {
+            current: state,
+            target: state,
+            ground_column: None,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct CameraBookmarks {
+    slots: [Option<CameraState>; 4],
+}
+
+const BOOKMARK_KEYS: [KeyCode; 4] = [KeyCode::F5, KeyCode::F6, KeyCode::F7, KeyCode::F8];
+
+fn spawn_camera(mut commands: Commands) {
+    let mut camera_transform = Transform::from_xyz(0.0, 300.0, 300.0);
+    camera_transform.look_at(Vec3::ZERO, Vec3::Y);
+    let camera = commands
+        .spawn(Camera3dBundle {
+            projection: Projection::Perspective(PerspectiveProjection {
+                fov: PI / 6.0,
+                aspect_ratio: 16.0 / 9.0,
+                near: 0.1,
+                far: 10000.0,
+            }),
+            transform: camera_transform,
+            ..default()
+        })
+        .id();
+    commands
+        .spawn((
+            GlobalTransform::default(),
+            Transform::from_xyz(0.0, 0.0, 0.0),
+            RtsCamera::new(Vec3::ZERO),
+        ))
+        .push_children(&[camera]);
+}
+
+pub fn camera_input(
+    mut rigs: Query<&mut RtsCamera>,
+    mut bookmarks: ResMut<CameraBookmarks>,
+    mut wheel: EventReader<MouseWheel>,
+    mut motion: EventReader<MouseMotion>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    time: Res<Time<Real>>,
+) {
+    let Ok(mut rig) = rigs.get_single_mut() else {
+        return;
+    };
+    let dt = time.delta_seconds();
+    let target = &mut rig.target;
+
+    for event in wheel.read() {
+        let lines = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 40.0,
+        };
+        target.zoom = (target.zoom - lines * ZOOM_STEP).clamp(0.0, 1.0);
+    }
+
+    let orbit = motion.read().map(|event| event.delta).sum::<Vec2>();
+    if buttons.pressed(MouseButton::Middle) {
+        target.yaw -= orbit.x * ORBIT_SENSITIVITY;
+        target.pitch_offset =
+            (target.pitch_offset + orbit.y * ORBIT_SENSITIVITY).clamp(-PI / 2.0, PI / 2.0);
+    }
+
+    let rotation = keys.pressed(KeyCode::E) as i32 - keys.pressed(KeyCode::Q) as i32;
+    target.yaw += dt * 0.25 * TAU * rotation as f32;
+
+    let mut lateral = Vec2::new(
+        (keys.pressed(KeyCode::D) as i32 - keys.pressed(KeyCode::A) as i32) as f32,
+        (keys.pressed(KeyCode::S) as i32 - keys.pressed(KeyCode::W) as i32) as f32,
+    );
+    let window = windows.get_single().ok();
+    if let Some((window, cursor)) = window.and_then(|w| w.cursor_position().map(|c| (w, c))) {
+        if cursor.x < EDGE_MARGIN {
+            lateral.x -= 1.0;
+        } else if cursor.x > window.width() - EDGE_MARGIN {
+            lateral.x += 1.0;
+        }
+        if cursor.y < EDGE_MARGIN {
+            lateral.y -= 1.0;
+        } else if cursor.y > window.height() - EDGE_MARGIN {
+            lateral.y += 1.0;
+        }
+    }
+    let lateral = lateral.clamp(Vec2::NEG_ONE, Vec2::ONE);
+    let direction = Quat::from_axis_angle(Vec3::Y, target.yaw)
+        * Vec3::new(lateral.x, 0.0, lateral.y).normalize_or_zero();
+    target.focus += direction * PAN_SPEED * target.distance() * dt;
+
+    for (slot, key) in BOOKMARK_KEYS.iter().enumerate() {
+        if !keys.just_pressed(*key) {
+            continue;
+        }
+        if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+            bookmarks.slots[slot] = Some(*target);
+        } else if let Some(bookmark) = bookmarks.slots[slot] {
+            *target = bookmark;
+        }
+    }
+}
+
+pub fn follow_ground(bevy_world: &mut bevy::prelude::World) {
+    let (entity, focus, ground_column) = {
+        let mut system_state = SystemState::<Query<(Entity, &RtsCamera)>>::new(bevy_world);
+        let query = system_state.get(bevy_world);
+        let Ok((entity, rig)) = query.get_single() else {
+            return;
+        };
+        (entity, rig.target.focus, rig.ground_column)
+    };
+    let column = focus.floor().as_ivec3().xz();
+    if ground_column == Some(column) {
+        return;
+    }
+
+    let Ok(ground) = column::surface_height(bevy_world, column) else {
+        return;
+    };
+    let mut rig = bevy_world.get_mut::<RtsCamera>(entity).unwrap();
+    rig.target.focus.y = ground as f32;
+    rig.ground_column = Some(column);
+}
+
+pub fn camera(
+    mut rigs: Query<(&mut RtsCamera, &mut Transform, &Children), Without<Camera>>,
+    mut cameras: Query<&mut Transform, With<Camera>>,
+    time: Res<Time<Real>>,
+) {
+    let Ok((mut rig, mut parent_transform, children)) = rigs.get_single_mut() else {
+        return;
+    };
+    let factor = 1.0 - (-DAMPING * time.delta_seconds()).exp();
+    let target = rig.target;
+    rig.current.damp(&target, factor);
+
+    parent_transform.translation = rig.current.focus;
+    parent_transform.rotation = Quat::from_axis_angle(Vec3::Y, rig.current.yaw);
+
+    let pitch = rig.current.pitch();
+    let offset = Vec3::new(0.0, pitch.sin(), pitch.cos()) * rig.current.distance();
+    for &child in children.iter() {
+        if let Ok(mut camera_transform) = cameras.get_mut(child) {
+            *camera_transform = Transform::from_translation(offset).looking_at(Vec3::ZERO, Vec3::Y);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use xenotech::terrain::DEFAULT_SEED;
+
+pub const USAGE: &str = "\
+usage: xenotech [--headless] [--seed <u32>] [--view <chunks>] [--ticks <n>] [--output <path>]
+
+  --headless        run generation and simulation without a window or renderer
+  --seed <u32>      terrain seed
+  --view <chunks>   view distance in chunks around the focus
+  --ticks <n>       simulation ticks to run once loading has settled (headless only)
+  --output <path>   write the generated world to <path> when done (headless only)";
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub headless: bool,
+    pub seed: u32,
+    pub view: usize,
+    pub ticks: u32,
+    pub output: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            headless: false,
+            seed: DEFAULT_SEED,
+            view: 12,
+            ticks: 0,
+            output: None,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut ticks = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {name}"))
+            };
+            match arg.as_str() {
+                "--headless" => options.headless = true,
+                "--seed" => options.seed = parse_number(&arg, value(&arg)?)?,
+                "--view" => options.view = parse_number(&arg, value(&arg)?)?,
+                "--ticks" => ticks = Some(parse_number(&arg, value(&arg)?)?),
+                "--output" => options.output = Some(PathBuf::from(value(&arg)?)),
+                _ => return Err(format!("unknown argument {arg}")),
+            }
+        }
+        if !options.headless && (ticks.is_some() || options.output.is_some()) {
+            return Err("--ticks and --output require --headless".to_string());
+        }
+        options.ticks = ticks.unwrap_or(0);
+        Ok(options)
+    }
+
+    pub fn from_env() -> Self {
+        let args = std::env::args().skip(1).collect::<Vec<_>>();
+        if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+            println!("{USAGE}");
+            std::process::exit(0);
+        }
+        Options::parse(args).unwrap_or_else(|error| {
+            eprintln!("{error}\n\n{USAGE}");
+            std::process::exit(2);
+        })
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: String) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {name}: {value}"))
+}
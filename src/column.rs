@@ -0,0 +1,163 @@
+use std::fmt;
+
+use bevy::prelude::*;
+
+use crate::Block;
+use crate::Structure;
+use crate::VoxelWorld;
+use crate::CHUNK_AXIS;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColumnError {
+    /// The chunk holding this part of the column has not been generated.
+    Unloaded(IVec3),
+}
+
+impl fmt::Display for ColumnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColumnError::Unloaded(position) => write!(f, "terrain at {position} is not loaded"),
+        }
+    }
+}
+
+/// Highest solid voxel in every column of a chunk, kept next to its
+/// `Structure` so that column queries only look at the voxels they need.
+#[derive(Component)]
+pub struct Heightmap {
+    size: UVec2,
+    /// Local height of the top solid voxel per column, `None` for columns of
+    /// air, indexed `z * size.x + x`.
+    tops: Vec<Option<u8>>,
+}
+
+impl Heightmap {
+    pub fn new(structure: &Structure) -> Self {
+        let size = structure.size();
+        let mut heightmap = Heightmap {
+            size: size.xz(),
+            tops: vec![None; (size.x * size.z) as usize],
+        };
+        match structure.uniform_block() {
+            Some(Block::Air) => {}
+            Some(_) => heightmap.tops.fill(Some(size.y as u8 - 1)),
+            None => {
+                for z in 0..size.z {
+                    for x in 0..size.x {
+                        heightmap.update(structure, UVec2::new(x, z));
+                    }
+                }
+            }
+        }
+        heightmap
+    }
+
+    pub fn top(&self, column: UVec2) -> Option<u32> {
+        self.tops[(column.y * self.size.x + column.x) as usize].map(u32::from)
+    }
+
+    /// Rescans `column` of `structure` after its blocks changed.
+    pub fn update(&mut self, structure: &Structure, column: UVec2) {
+        let top = (0..structure.size().y).rev().find(|&y| {
+            let position = UVec3::new(column.x, y, column.y);
+            structure.get_block([position]).next() != Some(Block::Air)
+        });
+        self.tops[(column.y * self.size.x + column.x) as usize] = top.map(|y| y as u8);
+    }
+}
+
+fn chunk_at(
+    bevy_world: &bevy::prelude::World,
+    chunk_position: IVec3,
+) -> Option<(&Structure, &Heightmap)> {
+    let world = bevy_world.resource::<VoxelWorld>();
+    let chunk_entity = world.chunk_entity(chunk_position)?;
+    Some((bevy_world.get(chunk_entity)?, bevy_world.get(chunk_entity)?))
+}
+
+/// Lowest and highest chunk generated for `chunk_column`, whose terrain
+/// surface lies between them.
+fn chunk_span(world: &VoxelWorld, chunk_column: IVec2) -> Option<(i32, i32)> {
+    let &(low, high) = world.columns.get(&chunk_column)?;
+    let chunk_position = |y: i32| IVec3::new(chunk_column.x, y, chunk_column.y);
+    // Neighboring columns can pull in chunks above this column's surface.
+    let mut top = high + 1;
+    while world.chunk_entity(chunk_position(top + 1)).is_some() {
+        top += 1;
+    }
+    Some((low - 1, top))
+}
+
+/// Height of the first air voxel above the highest solid voxel in `column`.
+pub fn surface_height(
+    bevy_world: &bevy::prelude::World,
+    column: IVec2,
+) -> Result<i32, ColumnError> {
+    let axis = CHUNK_AXIS as i32;
+    let chunk_column = column.div_euclid(IVec2::splat(axis));
+    let local = column.rem_euclid(IVec2::splat(axis)).as_uvec2();
+    let unloaded =
+        |chunk_y: i32| ColumnError::Unloaded(IVec3::new(column.x, chunk_y * axis, column.y));
+    let world = bevy_world.resource::<VoxelWorld>();
+    let (bottom, top) = chunk_span(world, chunk_column).ok_or(unloaded(0))?;
+    for chunk_y in (bottom..=top).rev() {
+        let chunk_position = IVec3::new(chunk_column.x, chunk_y, chunk_column.y);
+        let (_, heightmap) = chunk_at(bevy_world, chunk_position).ok_or(unloaded(chunk_y))?;
+        if let Some(y) = heightmap.top(local) {
+            return Ok(chunk_y * axis + y as i32 + 1);
+        }
+    }
+    Err(unloaded(bottom - 1))
+}
+
+/// The first solid voxel at or below `position`.
+pub fn solid_below(
+    bevy_world: &bevy::prelude::World,
+    position: IVec3,
+) -> Result<IVec3, ColumnError> {
+    let axis = CHUNK_AXIS as i32;
+    let chunk = position.div_euclid(IVec3::splat(axis));
+    let local = position.rem_euclid(IVec3::splat(axis)).as_uvec3();
+    let unloaded =
+        |chunk_y: i32| ColumnError::Unloaded(IVec3::new(position.x, chunk_y * axis, position.z));
+    let world = bevy_world.resource::<VoxelWorld>();
+    let (bottom, top) = chunk_span(world, chunk.xz()).ok_or(unloaded(chunk.y))?;
+    for chunk_y in (bottom..=chunk.y.min(top)).rev() {
+        let chunk_position = IVec3::new(chunk.x, chunk_y, chunk.z);
+        let (structure, heightmap) =
+            chunk_at(bevy_world, chunk_position).ok_or(unloaded(chunk_y))?;
+        let Some(top) = heightmap.top(local.xz()) else {
+            continue;
+        };
+        let start = if chunk_y == chunk.y {
+            local.y.min(top)
+        } else {
+            top
+        };
+        let solid = (0..=start).rev().find(|&y| {
+            let voxel = UVec3::new(local.x, y, local.z);
+            structure.get_block([voxel]).next() != Some(Block::Air)
+        });
+        if let Some(y) = solid {
+            return Ok(IVec3::new(
+                position.x,
+                chunk_y * axis + y as i32,
+                position.z,
+            ));
+        }
+    }
+    Err(unloaded(bottom - 1))
+}
+
+/// Surface heights of the columns from `min` to `max` inclusive, row by row
+/// along x.
+pub fn heightmap(
+    bevy_world: &bevy::prelude::World,
+    min: IVec2,
+    max: IVec2,
+) -> Result<Vec<i32>, ColumnError> {
+    (min.y..=max.y)
+        .flat_map(|z| (min.x..=max.x).map(move |x| IVec2::new(x, z)))
+        .map(|column| surface_height(bevy_world, column))
+        .collect()
+}
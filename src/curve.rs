@@ -0,0 +1,258 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy::utils::hashbrown::HashSet;
+
+use crate::road::draw_line;
+use crate::road::LineMode;
+
+/// Segments per voxel of length when flattening a curve.
+const STEPS_PER_VOXEL: f32 = 4.0;
+/// Segments used to estimate the length before flattening.
+const MIN_STEPS: usize = 8;
+/// Arc length between the samples joined up when voxelizing a curve.
+const VOXEL_SPACING: f32 = 0.5;
+
+/// Centerline of a placed road or track, parameterized over `0.0..=1.0`.
+pub trait Curve: Send + Sync {
+    fn point(&self, t: f32) -> Vec3;
+
+    /// Straight segments approximating the curve, a quarter voxel long or
+    /// shorter wherever it bends. Straight curves can return a single piece.
+    fn lines(&self) -> Vec<(Vec3, Vec3)> {
+        let at = |i: usize, steps: usize| self.point(i as f32 / steps as f32);
+        let estimate = (0..MIN_STEPS)
+            .map(|i| at(i, MIN_STEPS).distance(at(i + 1, MIN_STEPS)))
+            .sum::<f32>();
+        let steps = ((estimate * STEPS_PER_VOXEL).ceil() as usize).max(MIN_STEPS);
+        (0..steps)
+            .map(|i| (at(i, steps), at(i + 1, steps)))
+            .collect()
+    }
+
+    fn length(&self) -> f32 {
+        self.lines().iter().map(|(a, b)| a.distance(*b)).sum()
+    }
+}
+
+fn cubic([a, b, c, d]: [Vec3; 4], t: f32) -> Vec3 {
+    let s = 1.0 - t;
+    a * (s * s * s) + b * (3.0 * s * s * t) + c * (3.0 * s * t * t) + d * (t * t * t)
+}
+
+pub struct Line {
+    pub start: Vec3,
+    pub end: Vec3,
+}
+
+impl Curve for Line {
+    fn point(&self, t: f32) -> Vec3 {
+        self.start.lerp(self.end, t)
+    }
+
+    fn lines(&self) -> Vec<(Vec3, Vec3)> {
+        vec![(self.start, self.end)]
+    }
+}
+
+/// Circular arc from `start` through `middle` to `end` in the horizontal
+/// plane, rising linearly from the height of `start` to that of `end`.
+pub struct Arc {
+    start: Vec3,
+    end: Vec3,
+    /// Centre, radius, start angle and signed sweep; `None` when the three
+    /// points are collinear and the arc is a straight line.
+    circle: Option<(Vec2, f32, f32, f32)>,
+}
+
+impl Arc {
+    pub fn new(start: Vec3, middle: Vec3, end: Vec3) -> Self {
+        let (a, b, c) = (start.xz(), middle.xz(), end.xz());
+        let denominator = 2.0 * (b - a).perp_dot(c - a);
+        let circle = (denominator.abs() > 1e-4).then(|| {
+            let (ab, ac) = (b - a, c - a);
+            let center = a + Vec2::new(
+                ac.y * ab.length_squared() - ab.y * ac.length_squared(),
+                ab.x * ac.length_squared() - ac.x * ab.length_squared(),
+            ) / denominator;
+            let angle = |p: Vec2| (p - center).y.atan2((p - center).x);
+            let from = angle(a);
+            let through = (angle(b) - from).rem_euclid(TAU);
+            let to = (angle(c) - from).rem_euclid(TAU);
+            let sweep = if through < to { to } else { to - TAU };
+            (center, center.distance(a), from, sweep)
+        });
+        Arc { start, end, circle }
+    }
+}
+
+impl Curve for Arc {
+    fn point(&self, t: f32) -> Vec3 {
+        let Some((center, radius, from, sweep)) = self.circle else {
+            return self.start.lerp(self.end, t);
+        };
+        let angle = from + sweep * t;
+        let xz = center + Vec2::new(angle.cos(), angle.sin()) * radius;
+        Vec3::new(xz.x, self.start.y + (self.end.y - self.start.y) * t, xz.y)
+    }
+}
+
+pub struct CubicBezier {
+    pub points: [Vec3; 4],
+}
+
+impl Curve for CubicBezier {
+    fn point(&self, t: f32) -> Vec3 {
+        cubic(self.points, t)
+    }
+}
+
+/// Uniform Catmull-Rom spline through every point, evaluated as one cubic
+/// Bezier per span so that tangents match at the joins.
+pub struct CatmullRom {
+    pub points: Vec<Vec3>,
+}
+
+impl CatmullRom {
+    fn span(&self, i: usize) -> [Vec3; 4] {
+        let n = self.points.len();
+        let p = |i: usize| self.points[i.min(n - 1)];
+        let (p0, p1, p2, p3) = (p(i.saturating_sub(1)), p(i), p(i + 1), p(i + 2));
+        [p1, p1 + (p2 - p0) / 6.0, p2 - (p3 - p1) / 6.0, p2]
+    }
+}
+
+impl Curve for CatmullRom {
+    fn point(&self, t: f32) -> Vec3 {
+        let spans = self.points.len().saturating_sub(1);
+        if spans == 0 {
+            return self.points.first().copied().unwrap_or(Vec3::ZERO);
+        }
+        let u = t.clamp(0.0, 1.0) * spans as f32;
+        let i = (u.floor() as usize).min(spans - 1);
+        cubic(self.span(i), u - i as f32)
+    }
+}
+
+/// Distance along a curve mapped back to positions, for spacing things
+/// evenly regardless of how the curve is parameterized.
+pub struct ArcLength {
+    points: Vec<Vec3>,
+    /// Distance from the start to each point.
+    distances: Vec<f32>,
+}
+
+impl ArcLength {
+    pub fn new(curve: &dyn Curve) -> Self {
+        let lines = curve.lines();
+        let mut points = vec![lines.first().map_or(curve.point(0.0), |line| line.0)];
+        let mut distances = vec![0.0];
+        for (a, b) in lines {
+            distances.push(distances.last().unwrap() + a.distance(b));
+            points.push(b);
+        }
+        ArcLength { points, distances }
+    }
+
+    pub fn length(&self) -> f32 {
+        *self.distances.last().unwrap()
+    }
+
+    /// Position `distance` along the curve, clamped to its ends.
+    pub fn point_at(&self, distance: f32) -> Vec3 {
+        let distance = distance.clamp(0.0, self.length());
+        let i = self
+            .distances
+            .partition_point(|&d| d <= distance)
+            .clamp(1, self.points.len() - 1);
+        let span = self.distances[i] - self.distances[i - 1];
+        let t = if span > 0.0 {
+            (distance - self.distances[i - 1]) / span
+        } else {
+            0.0
+        };
+        self.points[i - 1].lerp(self.points[i], t)
+    }
+
+    /// Points every `spacing` along the curve, including both ends.
+    pub fn resample(&self, spacing: f32) -> Vec<Vec3> {
+        let length = self.length();
+        let count = (length / spacing).ceil().max(1.0) as usize;
+        (0..=count)
+            .map(|i| self.point_at(length * i as f32 / count as f32))
+            .collect()
+    }
+}
+
+/// Voxels along `curve` in order from its start, each listed once with the
+/// arc length at which the curve reaches it. Samples evenly spaced by arc
+/// length are joined with `LineMode::BOTH` lines, so the voxels are
+/// 6-connected with no gaps at diagonal steps.
+pub fn voxelize(curve: &dyn Curve) -> Vec<(IVec3, f32)> {
+    let arc_length = ArcLength::new(curve);
+    let length = arc_length.length();
+    let count = (length / VOXEL_SPACING).ceil().max(1.0) as usize;
+    let mut seen = HashSet::new();
+    let mut voxels = vec![];
+    let mut previous = None;
+    for i in 0..=count {
+        let distance = length * i as f32 / count as f32;
+        let voxel = arc_length.point_at(distance).round().as_ivec3();
+        draw_line(previous.unwrap_or(voxel), voxel, LineMode::BOTH, |pos| {
+            if seen.insert(pos) {
+                voxels.push((pos, distance));
+            }
+        });
+        previous = Some(voxel);
+    }
+    voxels
+}
+
+/// Shape the build tool fits through the clicked points.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CurveShape {
+    Straight,
+    Arc,
+    #[default]
+    Bezier,
+    Spline,
+}
+
+impl CurveShape {
+    pub fn next(self) -> Self {
+        match self {
+            CurveShape::Straight => CurveShape::Arc,
+            CurveShape::Arc => CurveShape::Bezier,
+            CurveShape::Bezier => CurveShape::Spline,
+            CurveShape::Spline => CurveShape::Straight,
+        }
+    }
+
+    /// Clicks that complete a curve of this shape. Splines take any number
+    /// of points from two up and are finished explicitly.
+    pub fn clicks(self) -> Option<usize> {
+        match self {
+            CurveShape::Straight => Some(2),
+            CurveShape::Arc => Some(3),
+            CurveShape::Bezier => Some(4),
+            CurveShape::Spline => None,
+        }
+    }
+
+    /// Fits a curve through `points`, or returns `None` unless they are
+    /// exactly `clicks()` points, or at least two for a spline.
+    pub fn build(self, points: &[Vec3]) -> Option<Box<dyn Curve>> {
+        let curve: Box<dyn Curve> = match (self, points) {
+            (CurveShape::Straight, &[start, end]) => Box::new(Line { start, end }),
+            (CurveShape::Arc, &[start, middle, end]) => Box::new(Arc::new(start, middle, end)),
+            (CurveShape::Bezier, &[a, b, c, d]) => Box::new(CubicBezier {
+                points: [a, b, c, d],
+            }),
+            (CurveShape::Spline, points) if points.len() >= 2 => Box::new(CatmullRom {
+                points: points.to_vec(),
+            }),
+            _ => return None,
+        };
+        Some(curve)
+    }
+}
@@ -0,0 +1,248 @@
+use std::f32::consts::PI;
+use std::f32::consts::TAU;
+
+use bevy::pbr::CascadeShadowConfig;
+use bevy::pbr::CascadeShadowConfigBuilder;
+use bevy::pbr::DirectionalLightShadowMap;
+use bevy::prelude::*;
+
+use crate::camera::RtsCamera;
+use crate::camera::MAX_DISTANCE;
+use crate::overlay::ChunkMaterial;
+use crate::overlay::ChunkOverlay;
+
+const SUN_DISTANCE: f32 = 1000.0;
+const SUN_ILLUMINANCE: f32 = 10000.0;
+const MOON_ILLUMINANCE: f32 = 400.0;
+const NIGHT_EPSILON: f32 = 0.01;
+/// Relative change in camera distance after which the shadow cascades are
+/// refitted.
+const CASCADE_REFIT: f32 = 0.05;
+
+/// Sun, moon and window lighting that follow the simulated `TimeOfDay`,
+/// plus the pause and speed controls for the clock.
+pub struct DayNightPlugin;
+
+impl Plugin for DayNightPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DirectionalLightShadowMap { size: 4096 })
+            .add_systems(Startup, setup_lights)
+            .add_systems(Update, time_controls.before(advance_time_of_day))
+            .add_systems(
+                Update,
+                (update_lighting, update_window_lighting)
+                    .chain()
+                    .after(advance_time_of_day),
+            )
+            .add_systems(Update, fit_shadow_cascades);
+    }
+}
+
+#[derive(Component)]
+pub struct Sun;
+
+#[derive(Component)]
+pub struct Moon;
+
+#[derive(Resource)]
+pub struct TimeOfDay {
+    pub hour: f32,
+    pub day_length: f32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        TimeOfDay {
+            hour: 9.0,
+            day_length: 600.0,
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = (self.hour / 24.0) * TAU - PI / 2.0;
+        Vec3::new(angle.cos(), angle.sin(), 0.35).normalize()
+    }
+
+    pub fn elevation(&self) -> f32 {
+        self.sun_direction().y
+    }
+
+    pub fn daylight(&self) -> f32 {
+        ((self.elevation() + 0.1) / 0.4).clamp(0.0, 1.0)
+    }
+
+    pub fn night(&self) -> f32 {
+        1.0 - self.daylight()
+    }
+}
+
+pub fn color_temperature(kelvin: f32) -> Color {
+    let t = kelvin / 100.0;
+    let red = if t <= 66.0 {
+        255.0
+    } else {
+        329.69873 * (t - 60.0).powf(-0.13320476)
+    };
+    let green = if t <= 66.0 {
+        99.4708 * t.ln() - 161.11957
+    } else {
+        288.12216 * (t - 60.0).powf(-0.07551485)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (t - 10.0).ln() - 305.0448
+    };
+    Color::rgb(
+        red.clamp(0.0, 255.0) / 255.0,
+        green.clamp(0.0, 255.0) / 255.0,
+        blue.clamp(0.0, 255.0) / 255.0,
+    )
+}
+
+pub fn time_controls(keys: Res<Input<KeyCode>>, mut time: ResMut<Time<Virtual>>) {
+    if keys.just_pressed(KeyCode::Space) {
+        if time.is_paused() {
+            time.unpause();
+        } else {
+            time.pause();
+        }
+    }
+    let speed = [(KeyCode::F1, 1.0), (KeyCode::F2, 2.0), (KeyCode::F3, 4.0)]
+        .into_iter()
+        .find(|(key, _)| keys.just_pressed(*key));
+    if let Some((_, speed)) = speed {
+        time.set_relative_speed(speed);
+        time.unpause();
+    }
+}
+
+pub fn advance_time_of_day(mut time_of_day: ResMut<TimeOfDay>, time: Res<Time>) {
+    let hours = time.delta_seconds() / time_of_day.day_length * 24.0;
+    time_of_day.hour = (time_of_day.hour + hours).rem_euclid(24.0);
+}
+
+pub fn update_lighting(
+    time_of_day: Res<TimeOfDay>,
+    mut ambient: ResMut<AmbientLight>,
+    mut sun: Query<(&mut Transform, &mut DirectionalLight), (With<Sun>, Without<Moon>)>,
+    mut moon: Query<(&mut Transform, &mut DirectionalLight), (With<Moon>, Without<Sun>)>,
+) {
+    let direction = time_of_day.sun_direction();
+    let daylight = time_of_day.daylight();
+
+    if let Ok((mut transform, mut light)) = sun.get_single_mut() {
+        *transform = Transform::from_translation(direction * SUN_DISTANCE)
+            .looking_at(Vec3::ZERO, Vec3::Y);
+        let kelvin = 2000.0 + 3800.0 * direction.y.max(0.0).sqrt();
+        light.color = color_temperature(kelvin);
+        light.illuminance = SUN_ILLUMINANCE * daylight;
+    }
+
+    if let Ok((mut transform, mut light)) = moon.get_single_mut() {
+        *transform = Transform::from_translation(-direction * SUN_DISTANCE)
+            .looking_at(Vec3::ZERO, Vec3::Y);
+        light.color = color_temperature(8000.0);
+        light.illuminance = MOON_ILLUMINANCE * (1.0 - daylight) * (-direction.y).max(0.0).sqrt();
+    }
+
+    let sky = color_temperature(4000.0 + 4000.0 * daylight).as_rgba_f32();
+    let night_sky = [0.35, 0.4, 0.7, 1.0];
+    let color = Vec4::from_array(night_sky).lerp(Vec4::from_array(sky), daylight);
+    ambient.color = Color::rgba(color.x, color.y, color.z, 1.0);
+    ambient.brightness = 0.03 + 0.12 * daylight;
+}
+
+pub fn update_window_lighting(
+    mut applied: Local<f32>,
+    time_of_day: Res<TimeOfDay>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    chunks: Query<Ref<ChunkOverlay>>,
+) {
+    let night = time_of_day.night();
+    let changed = (night - *applied).abs() > NIGHT_EPSILON;
+    if changed {
+        *applied = night;
+    }
+    for chunk_overlay in chunks.iter() {
+        if changed || chunk_overlay.is_added() {
+            chunk_overlay.set_night(&mut materials, *applied);
+        }
+    }
+}
+
+fn setup_lights(mut commands: Commands, time_of_day: Res<TimeOfDay>) {
+    let mut light_transform = Transform::from_xyz(1000.0, 1000.0, 1000.0);
+    light_transform.look_at(Vec3::ZERO, Vec3::Y);
+    let sun = DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            color: Color::Rgba {
+                red: 1.0,
+                green: 0.996,
+                blue: 0.976,
+                alpha: 1.0,
+            },
+            illuminance: 10000.0,
+            shadows_enabled: true,
+            ..default()
+        },
+        cascade_shadow_config: shadow_cascades(MAX_DISTANCE),
+        transform: light_transform,
+        ..default()
+    };
+    spawn_lights(&mut commands, &time_of_day, sun);
+}
+
+/// Shadow cascades for a camera `distance` from the point it looks at: the
+/// first cascade reaches just past that point, the last one beyond it.
+fn shadow_cascades(distance: f32) -> CascadeShadowConfig {
+    CascadeShadowConfigBuilder {
+        minimum_distance: 0.1,
+        first_cascade_far_bound: distance * 1.5,
+        maximum_distance: distance * 3.0,
+        ..default()
+    }
+    .build()
+}
+
+/// Refits the sun's shadow cascades to the camera as it zooms.
+pub fn fit_shadow_cascades(
+    mut fitted: Local<f32>,
+    rigs: Query<&RtsCamera>,
+    mut sun: Query<&mut CascadeShadowConfig, With<Sun>>,
+) {
+    let Ok(rig) = rigs.get_single() else {
+        return;
+    };
+    let distance = rig.current.distance();
+    if (distance - *fitted).abs() <= *fitted * CASCADE_REFIT {
+        return;
+    }
+    if let Ok(mut cascades) = sun.get_single_mut() {
+        *cascades = shadow_cascades(distance);
+        *fitted = distance;
+    }
+}
+
+pub fn spawn_lights(commands: &mut Commands, time_of_day: &TimeOfDay, sun: DirectionalLightBundle) {
+    commands.spawn((sun, Sun));
+    let moon_direction = -time_of_day.sun_direction();
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                color: color_temperature(8000.0),
+                illuminance: 0.0,
+                shadows_enabled: false,
+                ..default()
+            },
+            transform: Transform::from_translation(moon_direction * SUN_DISTANCE)
+                .looking_at(Vec3::ZERO, Vec3::Y),
+            ..default()
+        },
+        Moon,
+    ));
+}
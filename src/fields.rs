@@ -0,0 +1,258 @@
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+use bevy::utils::hashbrown::HashMap;
+
+use crate::CHUNK_AXIS;
+
+const EPSILON: f32 = 0.0001;
+
+pub trait FieldKind: Send + Sync + 'static {
+    const DIFFUSION: f32;
+    const DECAY: f32;
+    const DISPLAY_MAX: f32;
+}
+
+pub struct LandValue;
+pub struct Happiness;
+pub struct NoiseLevel;
+pub struct AirPollution;
+pub struct GroundPollution;
+
+impl FieldKind for LandValue {
+    const DIFFUSION: f32 = 0.6;
+    const DECAY: f32 = 0.02;
+    const DISPLAY_MAX: f32 = 10.0;
+}
+
+impl FieldKind for Happiness {
+    const DIFFUSION: f32 = 0.3;
+    const DECAY: f32 = 0.05;
+    const DISPLAY_MAX: f32 = 1.5;
+}
+
+impl FieldKind for NoiseLevel {
+    const DIFFUSION: f32 = 1.0;
+    const DECAY: f32 = 0.5;
+    const DISPLAY_MAX: f32 = 2.0;
+}
+
+impl FieldKind for AirPollution {
+    const DIFFUSION: f32 = 2.0;
+    const DECAY: f32 = 0.1;
+    const DISPLAY_MAX: f32 = 4.0;
+}
+
+impl FieldKind for GroundPollution {
+    const DIFFUSION: f32 = 0.05;
+    const DECAY: f32 = 0.005;
+    const DISPLAY_MAX: f32 = 4.0;
+}
+
+#[derive(Resource)]
+pub struct ScalarField2D<K: FieldKind> {
+    chunks: HashMap<IVec2, Vec<f32>>,
+    sources: HashMap<IVec2, f32>,
+    kind: PhantomData<K>,
+}
+
+impl<K: FieldKind> Default for ScalarField2D<K> {
+    fn default() -> Self {
+        ScalarField2D {
+            chunks: HashMap::new(),
+            sources: HashMap::new(),
+            kind: PhantomData,
+        }
+    }
+}
+
+impl<K: FieldKind> ScalarField2D<K> {
+    fn split(column: IVec2) -> (IVec2, usize) {
+        let chunk = column.div_euclid(IVec2::splat(CHUNK_AXIS as i32));
+        let local = column.rem_euclid(IVec2::splat(CHUNK_AXIS as i32));
+        (chunk, local.y as usize * CHUNK_AXIS + local.x as usize)
+    }
+
+    fn ensure(&mut self, chunk: IVec2) {
+        self.chunks
+            .entry(chunk)
+            .or_insert_with(|| vec![0.0; CHUNK_AXIS * CHUNK_AXIS]);
+    }
+
+    pub fn add_source(&mut self, column: IVec2, rate: f32) {
+        let entry = self.sources.entry(column).or_insert(0.0);
+        *entry += rate;
+        if entry.abs() < EPSILON {
+            self.sources.remove(&column);
+        }
+    }
+
+    pub fn add_sink(&mut self, column: IVec2, rate: f32) {
+        self.add_source(column, -rate);
+    }
+
+    pub fn get(&self, column: IVec2) -> f32 {
+        let (chunk, index) = Self::split(column);
+        self.chunks
+            .get(&chunk)
+            .map(|values| values[index])
+            .unwrap_or(0.0)
+    }
+
+    pub fn set(&mut self, column: IVec2, value: f32) {
+        let (chunk, index) = Self::split(column);
+        self.ensure(chunk);
+        self.chunks.get_mut(&chunk).unwrap()[index] = value;
+    }
+
+    pub fn sample(&self, position: Vec2) -> f32 {
+        let position = position - 0.5;
+        let base = position.floor();
+        let t = position - base;
+        let base = base.as_ivec2();
+        let a = self.get(base);
+        let b = self.get(base + IVec2::X);
+        let c = self.get(base + IVec2::Y);
+        let d = self.get(base + IVec2::ONE);
+        let top = a + (b - a) * t.x;
+        let bottom = c + (d - c) * t.x;
+        top + (bottom - top) * t.y
+    }
+
+    pub fn average(&self, min: IVec2, max: IVec2) -> f32 {
+        let mut total = 0.0;
+        let mut count = 0;
+        for z in min.y..=max.y {
+            for x in min.x..=max.x {
+                total += self.get(IVec2::new(x, z));
+                count += 1;
+            }
+        }
+        if count == 0 {
+            0.0
+        } else {
+            total / count as f32
+        }
+    }
+
+    pub fn normalized(&self, column: IVec2) -> f32 {
+        (self.get(column) / K::DISPLAY_MAX).clamp(0.0, 1.0)
+    }
+
+    pub fn chunk_columns(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn step(&mut self, dt: f32) {
+        let source_chunks = self
+            .sources
+            .keys()
+            .map(|&column| Self::split(column).0)
+            .collect::<Vec<_>>();
+        for chunk in source_chunks {
+            self.ensure(chunk);
+        }
+
+        let diffusion = (K::DIFFUSION * dt).min(0.25);
+        let decay = (1.0 - K::DECAY * dt).max(0.0);
+        let axis = CHUNK_AXIS as i32;
+
+        let mut next = HashMap::new();
+        let mut grow = vec![];
+        for (&chunk, values) in self.chunks.iter() {
+            let mut stepped = vec![0.0; CHUNK_AXIS * CHUNK_AXIS];
+            let mut alive = false;
+            for z in 0..axis {
+                for x in 0..axis {
+                    let index = (z * axis + x) as usize;
+                    let column = chunk * axis + IVec2::new(x, z);
+                    let value = values[index];
+                    let neighbor = |offset: IVec2| {
+                        let local = IVec2::new(x, z) + offset;
+                        if local.cmpge(IVec2::ZERO).all() && local.cmplt(IVec2::splat(axis)).all() {
+                            values[(local.y * axis + local.x) as usize]
+                        } else {
+                            self.get(column + offset)
+                        }
+                    };
+                    let laplacian = neighbor(IVec2::X)
+                        + neighbor(IVec2::NEG_X)
+                        + neighbor(IVec2::Y)
+                        + neighbor(IVec2::NEG_Y)
+                        - 4.0 * value;
+                    let source = self.sources.get(&column).copied().unwrap_or(0.0);
+                    let value = ((value + diffusion * laplacian) * decay + source * dt).max(0.0);
+                    if value > EPSILON {
+                        alive = true;
+                        let edge = [
+                            (x == 0, IVec2::NEG_X),
+                            (x == axis - 1, IVec2::X),
+                            (z == 0, IVec2::NEG_Y),
+                            (z == axis - 1, IVec2::Y),
+                        ];
+                        for (on_edge, direction) in edge {
+                            if on_edge {
+                                grow.push(chunk + direction);
+                            }
+                        }
+                    }
+                    stepped[index] = value;
+                }
+            }
+            if alive {
+                next.insert(chunk, stepped);
+            }
+        }
+        self.chunks = next;
+        for chunk in grow {
+            self.ensure(chunk);
+        }
+    }
+}
+
+pub fn step_field<K: FieldKind>(mut field: ResMut<ScalarField2D<K>>, time: Res<Time>) {
+    field.step(time.delta_seconds());
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum FieldLayer {
+    LandValue,
+    Happiness,
+    Noise,
+    AirPollution,
+    GroundPollution,
+}
+
+impl FieldLayer {
+    pub const ALL: [FieldLayer; 5] = [
+        FieldLayer::LandValue,
+        FieldLayer::Happiness,
+        FieldLayer::Noise,
+        FieldLayer::AirPollution,
+        FieldLayer::GroundPollution,
+    ];
+}
+
+pub fn heatmap(value: f32) -> Vec4 {
+    let stops = [
+        Vec4::new(0.1, 0.1, 0.6, 1.0),
+        Vec4::new(0.1, 0.7, 0.8, 1.0),
+        Vec4::new(0.2, 0.8, 0.2, 1.0),
+        Vec4::new(0.95, 0.85, 0.1, 1.0),
+        Vec4::new(0.9, 0.1, 0.05, 1.0),
+    ];
+    let scaled = value.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let index = (scaled.floor() as usize).min(stops.len() - 2);
+    stops[index].lerp(stops[index + 1], scaled - index as f32)
+}
+
+pub fn register_road_sources(
+    noise: &mut ScalarField2D<NoiseLevel>,
+    air: &mut ScalarField2D<AirPollution>,
+    land_value: &mut ScalarField2D<LandValue>,
+    column: IVec2,
+) {
+    noise.add_source(column, 0.2);
+    air.add_source(column, 0.05);
+    land_value.add_source(column, 0.02);
+}
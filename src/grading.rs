@@ -0,0 +1,277 @@
+use std::fmt;
+
+use bevy::prelude::*;
+
+use crate::column::surface_height;
+use crate::terrain::generated_height;
+use crate::terrain::Terrain;
+use crate::Block;
+
+/// Samples on each side averaged when smoothing the terrain profile.
+const SMOOTHING_RADIUS: usize = 6;
+/// Deck height above the terrain at which a road becomes a bridge.
+pub const BRIDGE_CLEARANCE: i32 = 4;
+/// Clear height inside a tunnel, above the road surface.
+pub const TUNNEL_HEIGHT: i32 = 4;
+/// Depth below the terrain at which a road becomes a tunnel instead of a cut.
+pub const TUNNEL_DEPTH: i32 = TUNNEL_HEIGHT + 2;
+/// Samples between bridge pillars.
+const PILLAR_SPACING: usize = 8;
+pub const MAX_PILLAR_HEIGHT: i32 = 48;
+/// Longest unsupported bridge span, between pillars or abutments.
+pub const MAX_SPAN: f32 = 24.0;
+pub const MAX_TUNNEL_LENGTH: f32 = 160.0;
+/// Air cleared above the road surface in cuts, so the sides of the road are
+/// not left buried where the terrain rises across it.
+const CLEARANCE: i32 = 3;
+
+const GRADE_COST: f32 = 1.0;
+const BRIDGE_COST: f32 = 6.0;
+const TUNNEL_COST: f32 = 10.0;
+const PILLAR_COST: f32 = 0.5;
+const EARTHWORK_COST: f32 = 0.2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Section {
+    Grade,
+    Bridge,
+    Tunnel,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProfileSample {
+    /// Road surface voxel.
+    pub deck: IVec3,
+    /// First air voxel above the terrain in this column.
+    pub ground: i32,
+    pub section: Section,
+    /// Horizontal distance from the start of the road.
+    pub distance: f32,
+}
+
+#[derive(Debug)]
+pub struct RoadPlan {
+    pub samples: Vec<ProfileSample>,
+    /// Pillar footprints as `(top, height)`; the pillar fills downwards from
+    /// the voxel below the deck.
+    pub pillars: Vec<(IVec3, i32)>,
+    pub cost: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlanError {
+    SpanTooLong { start: IVec3, span: f32 },
+    TunnelTooLong { start: IVec3, length: f32 },
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanError::SpanTooLong { start, span } => write!(
+                f,
+                "bridge at {start} needs an unsupported span of {span:.0} (max {MAX_SPAN})"
+            ),
+            PlanError::TunnelTooLong { start, length } => write!(
+                f,
+                "tunnel at {start} is {length:.0} long (max {MAX_TUNNEL_LENGTH})"
+            ),
+        }
+    }
+}
+
+/// Smooths a terrain profile and limits it to `max_grade`, keeping both ends
+/// on the terrain.
+pub fn grade_profile(ground: &[i32], distances: &[f32], max_grade: f32) -> Vec<i32> {
+    let n = ground.len();
+    if n == 0 {
+        return vec![];
+    }
+    let mut height = (0..n)
+        .map(|i| {
+            let window = i.saturating_sub(SMOOTHING_RADIUS)..(i + SMOOTHING_RADIUS + 1).min(n);
+            let len = window.len() as f32;
+            ground[window].iter().map(|&g| g as f32).sum::<f32>() / len
+        })
+        .collect::<Vec<_>>();
+    height[0] = ground[0] as f32;
+    height[n - 1] = ground[n - 1] as f32;
+
+    for i in 1..n {
+        let limit = max_grade * (distances[i] - distances[i - 1]);
+        height[i] = height[i].clamp(height[i - 1] - limit, height[i - 1] + limit);
+    }
+    height[n - 1] = ground[n - 1] as f32;
+    for i in (0..n - 1).rev() {
+        let limit = max_grade * (distances[i + 1] - distances[i]);
+        height[i] = height[i].clamp(height[i + 1] - limit, height[i + 1] + limit);
+    }
+    height.into_iter().map(|h| h.round() as i32).collect()
+}
+
+fn classify(deck: i32, ground: i32) -> Section {
+    if deck - ground >= BRIDGE_CLEARANCE {
+        Section::Bridge
+    } else if ground - deck >= TUNNEL_DEPTH {
+        Section::Tunnel
+    } else {
+        Section::Grade
+    }
+}
+
+/// Grades a road or track along `path`, a list of connected columns reached
+/// at `distances` along its centerline, to `max_grade` and decides where it
+/// needs bridges and tunnels. Columns that have not been generated yet are
+/// planned over the terrain the generator will produce there.
+pub fn plan_road(
+    bevy_world: &bevy::prelude::World,
+    path: &[IVec2],
+    distances: &[f32],
+    max_grade: f32,
+) -> Result<RoadPlan, PlanError> {
+    let seed = bevy_world.resource::<Terrain>().seed;
+    let ground = path
+        .iter()
+        .map(|&column| {
+            surface_height(bevy_world, column).unwrap_or_else(|_| generated_height(seed, column))
+        })
+        .collect::<Vec<_>>();
+
+    let decks = grade_profile(&ground, distances, max_grade);
+    let samples = path
+        .iter()
+        .zip(decks)
+        .zip(ground.iter().zip(distances))
+        .map(|((column, deck), (&ground, &distance))| ProfileSample {
+            deck: IVec3::new(column.x, deck, column.y),
+            ground,
+            section: classify(deck, ground),
+            distance,
+        })
+        .collect::<Vec<_>>();
+
+    let mut pillars = vec![];
+    let mut cost = 0.0;
+    for run in sections(&samples) {
+        let first = &samples[run.start];
+        let last = &samples[run.end - 1];
+        let length = last.distance - first.distance;
+        match first.section {
+            Section::Grade => {
+                cost += length * GRADE_COST;
+                cost += samples[run]
+                    .iter()
+                    .map(|sample| (sample.deck.y - sample.ground).abs() as f32)
+                    .sum::<f32>()
+                    * EARTHWORK_COST;
+            }
+            Section::Tunnel => {
+                if length > MAX_TUNNEL_LENGTH {
+                    return Err(PlanError::TunnelTooLong {
+                        start: first.deck,
+                        length,
+                    });
+                }
+                cost += length * TUNNEL_COST;
+            }
+            Section::Bridge => {
+                let mut support = first.distance;
+                for (offset, sample) in samples[run.clone()].iter().enumerate() {
+                    let height = sample.deck.y - sample.ground;
+                    let end = run.start + offset + 1 == run.end;
+                    if (offset % PILLAR_SPACING == 0 || end) && height <= MAX_PILLAR_HEIGHT {
+                        if sample.distance - support > MAX_SPAN {
+                            return Err(PlanError::SpanTooLong {
+                                start: first.deck,
+                                span: sample.distance - support,
+                            });
+                        }
+                        support = sample.distance;
+                        pillars.push((sample.deck - IVec3::Y, height));
+                        cost += height as f32 * PILLAR_COST;
+                    }
+                }
+                if last.distance - support > MAX_SPAN {
+                    return Err(PlanError::SpanTooLong {
+                        start: first.deck,
+                        span: last.distance - support,
+                    });
+                }
+                cost += length * BRIDGE_COST;
+            }
+        }
+    }
+
+    Ok(RoadPlan {
+        samples,
+        pillars,
+        cost,
+    })
+}
+
+/// Index ranges of consecutive samples that share a section type.
+fn sections(samples: &[ProfileSample]) -> Vec<std::ops::Range<usize>> {
+    let mut runs = vec![];
+    let mut start = 0;
+    for i in 1..=samples.len() {
+        if i == samples.len() || samples[i].section != samples[start].section {
+            runs.push(start..i);
+            start = i;
+        }
+    }
+    runs
+}
+
+fn perpendicular(samples: &[ProfileSample], i: usize) -> IVec3 {
+    let before = samples[i.saturating_sub(1)].deck;
+    let after = samples[(i + 1).min(samples.len() - 1)].deck;
+    let along = (after - before).xz();
+    if along.x.abs() >= along.y.abs() {
+        IVec3::Z
+    } else {
+        IVec3::X
+    }
+}
+
+impl RoadPlan {
+    /// Terrain edits the plan needs besides the road surface itself: cuts,
+    /// embankments, pillars and tunnel bores, for a road reaching
+    /// `half_width` voxels either side of the centerline.
+    pub fn earthworks(&self, half_width: i32, mut f: impl FnMut(IVec3, Block)) {
+        for (i, sample) in self.samples.iter().enumerate() {
+            let deck = sample.deck;
+            let side = perpendicular(&self.samples, i);
+            match sample.section {
+                Section::Grade => {
+                    for k in -half_width..=half_width {
+                        let column = deck + side * k;
+                        for y in sample.ground..deck.y {
+                            (f)(IVec3::new(column.x, y, column.z), Block::Stone);
+                        }
+                        for y in deck.y + 1..sample.ground.max(deck.y + CLEARANCE) {
+                            (f)(IVec3::new(column.x, y, column.z), Block::Air);
+                        }
+                    }
+                }
+                Section::Tunnel => {
+                    for k in -half_width..=half_width {
+                        let column = deck + side * k;
+                        for y in 1..=TUNNEL_HEIGHT {
+                            (f)(column + IVec3::Y * y, Block::Air);
+                        }
+                        (f)(column + IVec3::Y * (TUNNEL_HEIGHT + 1), Block::Stone);
+                    }
+                    for y in 0..=TUNNEL_HEIGHT + 1 {
+                        (f)(deck + side * (half_width + 1) + IVec3::Y * y, Block::Stone);
+                        (f)(deck - side * (half_width + 1) + IVec3::Y * y, Block::Stone);
+                    }
+                }
+                Section::Bridge => {}
+            }
+        }
+        for &(top, height) in &self.pillars {
+            for y in 0..height {
+                (f)(top - IVec3::Y * y, Block::Stone);
+            }
+        }
+    }
+}
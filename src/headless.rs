@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use xenotech::save::save_world;
+use xenotech::world::Dirty;
+use xenotech::VoxelWorld;
+
+use crate::cli::Options;
+
+/// Simulated time per headless tick, independent of how fast the host runs.
+pub const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+pub fn configure(app: &mut App) {
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(TICK));
+}
+
+pub fn run(mut app: App, options: &Options) {
+    app.finish();
+    app.cleanup();
+
+    let mut loading_updates = 0u64;
+    loop {
+        app.update();
+        loading_updates += 1;
+        if app.world.resource::<VoxelWorld>().is_settled() {
+            break;
+        }
+    }
+    for _ in 0..options.ticks {
+        app.update();
+    }
+
+    let chunks = app.world.resource::<VoxelWorld>().chunk_count();
+    let dirty = app
+        .world
+        .query_filtered::<(), With<Dirty>>()
+        .iter(&app.world)
+        .count();
+    println!(
+        "seed {}: {} chunks loaded in {} updates ({} unconsolidated at the edge), {} ticks simulated",
+        options.seed, chunks, loading_updates, dirty, options.ticks
+    );
+
+    if let Some(path) = &options.output {
+        match save_world(&mut app.world, path) {
+            Ok(()) => println!("saved world to {}", path.display()),
+            Err(error) => {
+                eprintln!("failed to save world to {}: {error}", path.display());
+                std::process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use std::f32::consts::FRAC_PI_4;
+
+use bevy::prelude::*;
+use bevy::utils::hashbrown::HashMap;
+use bitflags::bitflags;
+
+use crate::network::RoadNetwork;
+use crate::network::SegmentId;
+use crate::profile::RoadProfile;
+use crate::Block;
+use crate::CursorHit;
+
+/// Chebyshev radius around the centre searched for roads meeting there.
+const JUNCTION_RADIUS: i32 = 2;
+/// Columns beyond the paved square over which the corners taper back to the
+/// carriageway width.
+const FLARE_LENGTH: i32 = 2;
+/// Radius of the central island of a roundabout.
+const ROUNDABOUT_RADIUS: i32 = 4;
+/// Path samples walked from the crossing to find the direction of an arm.
+const ARM_REACH: usize = 6;
+/// Crossings closer than this to an existing junction extend it instead of
+/// creating a new one.
+const MERGE_DISTANCE: i32 = 6;
+/// Height difference above which two roads pass over each other rather than
+/// meet.
+const MAX_STEP: i32 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum JunctionControl {
+    Uncontrolled,
+    StopSigns,
+    TrafficLights,
+    Roundabout,
+}
+
+impl JunctionControl {
+    fn next(self) -> Self {
+        match self {
+            JunctionControl::Uncontrolled => JunctionControl::StopSigns,
+            JunctionControl::StopSigns => JunctionControl::TrafficLights,
+            JunctionControl::TrafficLights => JunctionControl::Uncontrolled,
+            JunctionControl::Roundabout => JunctionControl::Roundabout,
+        }
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+    pub struct TurnRestrictions: u8 {
+        const NO_LEFT =     0b0001;
+        const NO_RIGHT =    0b0010;
+        const NO_STRAIGHT = 0b0100;
+        const NO_U_TURN =   0b1000;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Turn {
+    Straight,
+    Left,
+    Right,
+    UTurn,
+}
+
+#[derive(Clone, Debug)]
+pub struct Junction {
+    /// Road surface voxel at the centre.
+    pub center: IVec3,
+    pub roads: Vec<SegmentId>,
+    /// Unit directions (8-neighbourhood) of the roads leaving the junction,
+    /// sorted by angle.
+    pub arms: Vec<IVec2>,
+    pub control: JunctionControl,
+    pub restrictions: TurnRestrictions,
+}
+
+impl Junction {
+    /// The turn a vehicle makes entering along arm `from` and leaving along
+    /// arm `to`.
+    pub fn turn(from: IVec2, to: IVec2) -> Turn {
+        let heading = -from;
+        let angle = (to.x * heading.y - to.y * heading.x) as f32;
+        let angle = angle.atan2(heading.dot(to) as f32);
+        if from == to || angle.abs() > 3.0 * FRAC_PI_4 {
+            Turn::UTurn
+        } else if angle.abs() <= FRAC_PI_4 {
+            Turn::Straight
+        } else if angle > 0.0 {
+            Turn::Left
+        } else {
+            Turn::Right
+        }
+    }
+
+    pub fn allows(&self, from: IVec2, to: IVec2) -> bool {
+        if !self.arms.contains(&from) || !self.arms.contains(&to) {
+            return false;
+        }
+        let restriction = match Junction::turn(from, to) {
+            Turn::Straight => TurnRestrictions::NO_STRAIGHT,
+            Turn::Left => TurnRestrictions::NO_LEFT,
+            Turn::Right => TurnRestrictions::NO_RIGHT,
+            Turn::UTurn => TurnRestrictions::NO_U_TURN,
+        };
+        !self.restrictions.contains(restriction)
+    }
+
+    /// The arm pointing closest to `offset` from the centre.
+    fn arm_toward(&self, offset: IVec2) -> Option<IVec2> {
+        let alignment = |arm: &IVec2| arm.as_vec2().normalize().dot(offset.as_vec2());
+        self.arms
+            .iter()
+            .copied()
+            .max_by(|a, b| alignment(a).total_cmp(&alignment(b)))
+    }
+
+    fn default_control(&self) -> JunctionControl {
+        match self.arms.len() {
+            0..=2 => JunctionControl::Uncontrolled,
+            3 => JunctionControl::StopSigns,
+            _ => JunctionControl::TrafficLights,
+        }
+    }
+
+    /// Blocks making up the junction surface for roads of `profile`: the
+    /// paved centre or roundabout ring, flared corners and a crosswalk across
+    /// every arm. Markings, curbs and sidewalks of the crossing roads are
+    /// paved over.
+    pub fn geometry(&self, profile: &RoadProfile, mut f: impl FnMut(IVec3, Block)) {
+        let center = self.center;
+        let at = |offset: IVec2| center + IVec3::new(offset.x, 0, offset.y);
+        let carriageway = profile.carriageway();
+        let (inner, outer) = if self.control == JunctionControl::Roundabout {
+            (
+                ROUNDABOUT_RADIUS as f32 - 0.5,
+                (ROUNDABOUT_RADIUS + carriageway) as f32 + 0.5,
+            )
+        } else {
+            (-1.0, carriageway as f32 + 0.5)
+        };
+        let reach = outer.floor() as i32;
+        for z in -reach..=reach {
+            for x in -reach..=reach {
+                let offset = IVec2::new(x, z);
+                let distance = if self.control == JunctionControl::Roundabout {
+                    offset.as_vec2().length()
+                } else {
+                    x.abs().max(z.abs()) as f32
+                };
+                if distance < inner {
+                    (f)(at(offset), Block::Grass);
+                    (f)(at(offset) + IVec3::Y, Block::Grass);
+                } else if distance <= outer {
+                    (f)(at(offset), Block::Asphalt);
+                    (f)(at(offset) + IVec3::Y, Block::Air);
+                }
+            }
+        }
+
+        for &arm in &self.arms {
+            let side = IVec2::new(-arm.y, arm.x);
+            for step in 1..=FLARE_LENGTH {
+                let width = carriageway + FLARE_LENGTH - step + 1;
+                for k in -width..=width {
+                    let offset = arm * (reach + step) + side * k;
+                    (f)(at(offset), Block::Asphalt);
+                    (f)(at(offset) + IVec3::Y, Block::Air);
+                }
+            }
+            for k in -carriageway..=carriageway {
+                (f)(
+                    at(arm * (reach + FLARE_LENGTH + 1) + side * k),
+                    Block::Crosswalk,
+                );
+            }
+        }
+    }
+}
+
+/// Junctions on the road network, keyed by their centre column. The traffic
+/// simulation reads control and turn restrictions from here.
+#[derive(Resource, Default)]
+pub struct Junctions {
+    junctions: HashMap<IVec2, Junction>,
+}
+
+impl Junctions {
+    pub fn get(&self, column: IVec2) -> Option<&Junction> {
+        self.junctions.get(&column)
+    }
+
+    /// The junction whose centre is closest to `column`, within `radius`.
+    pub fn near(&self, column: IVec2, radius: i32) -> Option<&Junction> {
+        self.nearest(column, radius)
+            .map(|center| &self.junctions[&center])
+    }
+
+    pub fn near_mut(&mut self, column: IVec2, radius: i32) -> Option<&mut Junction> {
+        self.nearest(column, radius)
+            .and_then(|center| self.junctions.get_mut(&center))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Junction> {
+        self.junctions.values()
+    }
+
+    /// Shortest path over `network` from `from` to `to` that only turns
+    /// where the junctions it passes through allow it to. A path turns at a
+    /// junction from the arm it enters the junction's square by to the arm
+    /// it leaves by.
+    pub fn route(&self, network: &RoadNetwork, from: IVec2, to: IVec2) -> Option<Vec<IVec2>> {
+        let inside = |column: IVec2| self.near(column, JUNCTION_RADIUS);
+        // The state is the arm the path entered the junction it is in by.
+        network.route_with(from, to, |entry: Option<[i32; 2]>, column, next| {
+            let (current, ahead) = (inside(column), inside(next));
+            if current.map(|junction| junction.center) == ahead.map(|junction| junction.center) {
+                return Some(entry);
+            }
+            if let (Some(junction), Some(entry)) = (current, entry) {
+                let exit = junction.arm_toward(next - junction.center.xz());
+                if !exit.is_some_and(|exit| junction.allows(IVec2::from_array(entry), exit)) {
+                    return None;
+                }
+            }
+            Some(
+                ahead
+                    .and_then(|junction| junction.arm_toward(column - junction.center.xz()))
+                    .map(|arm| arm.to_array()),
+            )
+        })
+    }
+
+    fn nearest(&self, column: IVec2, radius: i32) -> Option<IVec2> {
+        self.junctions
+            .keys()
+            .map(|&center| (center, (center - column).abs().max_element()))
+            .filter(|&(_, distance)| distance <= radius)
+            .min_by_key(|&(center, distance)| (distance, center.to_array()))
+            .map(|(center, _)| center)
+    }
+
+    /// Creates or extends the junction at `center` from the roads that cross
+    /// it, returning the updated junction.
+    pub fn connect(&mut self, network: &RoadNetwork, center: IVec3, roundabout: bool) -> &Junction {
+        let key = self
+            .nearest(center.xz(), MERGE_DISTANCE)
+            .unwrap_or(center.xz());
+        let junction = self.junctions.entry(key).or_insert_with(|| Junction {
+            center,
+            roads: vec![],
+            arms: vec![],
+            control: JunctionControl::Uncontrolled,
+            restrictions: TurnRestrictions::empty(),
+        });
+
+        for z in -JUNCTION_RADIUS..=JUNCTION_RADIUS {
+            for x in -JUNCTION_RADIUS..=JUNCTION_RADIUS {
+                let column = junction.center.xz() + IVec2::new(x, z);
+                if let Some(road) = network.segment_at(column) {
+                    if !junction.roads.contains(&road) {
+                        junction.roads.push(road);
+                    }
+                }
+            }
+        }
+        junction.arms = junction
+            .roads
+            .iter()
+            .flat_map(|&road| arms(network.segment(road), junction.center.xz()))
+            .collect();
+        junction.arms.sort_by(|a, b| {
+            let angle = |v: &IVec2| (v.y as f32).atan2(v.x as f32);
+            angle(a).total_cmp(&angle(b))
+        });
+        junction.arms.dedup();
+
+        if roundabout {
+            junction.control = JunctionControl::Roundabout;
+        } else if junction.control != JunctionControl::Roundabout {
+            junction.control = junction.default_control();
+        }
+        junction
+    }
+}
+
+/// Centres of the places where `decks` runs over columns already on the road
+/// network at roughly the same height.
+pub fn crossings(network: &RoadNetwork, decks: &[IVec3]) -> Vec<IVec3> {
+    let touches = |deck: &IVec3| {
+        network
+            .height(deck.xz())
+            .is_some_and(|height| (height - deck.y).abs() <= MAX_STEP)
+    };
+    let mut centers = vec![];
+    let mut start = None;
+    for i in 0..=decks.len() {
+        match (start, decks.get(i).is_some_and(touches)) {
+            (None, true) => start = Some(i),
+            (Some(first), false) => {
+                centers.push(decks[(first + i - 1) / 2]);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    centers
+}
+
+/// Directions in which `path` leaves `center`, one per side the path extends
+/// past it.
+fn arms(path: &[IVec2], center: IVec2) -> Vec<IVec2> {
+    let Some(closest) = (0..path.len()).min_by_key(|&i| (path[i] - center).abs().max_element())
+    else {
+        return vec![];
+    };
+    let mut arms = vec![];
+    if closest >= ARM_REACH {
+        arms.push(octant(path[closest - ARM_REACH] - center));
+    }
+    if closest + ARM_REACH < path.len() {
+        arms.push(octant(path[closest + ARM_REACH] - center));
+    }
+    arms
+}
+
+/// `v` rounded to the nearest of the eight compass directions.
+fn octant(v: IVec2) -> IVec2 {
+    let angle = (v.y as f32).atan2(v.x as f32);
+    let angle = (angle / FRAC_PI_4).round() * FRAC_PI_4;
+    IVec2::new(angle.cos().round() as i32, angle.sin().round() as i32)
+}
+
+/// T cycles the control of the junction under the cursor; J, K, L and U
+/// toggle its bans on left turns, going straight, right turns and U-turns.
+pub fn junction_input(
+    keys: Res<Input<KeyCode>>,
+    hit: Res<CursorHit>,
+    mut junctions: ResMut<Junctions>,
+) {
+    let cycle = keys.just_pressed(KeyCode::T);
+    let toggled = [
+        (KeyCode::J, TurnRestrictions::NO_LEFT),
+        (KeyCode::K, TurnRestrictions::NO_STRAIGHT),
+        (KeyCode::L, TurnRestrictions::NO_RIGHT),
+        (KeyCode::U, TurnRestrictions::NO_U_TURN),
+    ]
+    .into_iter()
+    .filter(|(key, _)| keys.just_pressed(*key))
+    .fold(TurnRestrictions::empty(), |all, (_, ban)| all | ban);
+    if !cycle && toggled.is_empty() {
+        return;
+    }
+    let Some(hit) = hit.0 else {
+        return;
+    };
+    if let Some(junction) = junctions.near_mut(hit.voxel.xz(), MERGE_DISTANCE) {
+        if cycle {
+            junction.control = junction.control.next();
+        }
+        junction.restrictions.toggle(toggled);
+        info!(
+            "junction at {}: {:?}, {:?}",
+            junction.center, junction.control, junction.restrictions
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+
+pub mod block;
+pub mod block_tool;
+pub mod camera;
+pub mod column;
+pub mod curve;
+pub mod daynight;
+pub mod fields;
+pub mod grading;
+pub mod junction;
+pub mod lod;
+pub mod meshing;
+pub mod network;
+pub mod overlay;
+pub mod palette;
+pub mod picking;
+pub mod profile;
+pub mod road;
+pub mod save;
+pub mod services;
+pub mod structure;
+pub mod terrain;
+pub mod transit;
+pub mod validation;
+pub mod world;
+#[cfg(test)]
+mod tests;
+
+pub use block::Block;
+pub use camera::CameraPlugin;
+pub use daynight::DayNightPlugin;
+pub use meshing::MeshingPlugin;
+pub use picking::CursorHit;
+pub use picking::RayExt;
+pub use picking::VoxelHit;
+pub use road::RoadToolPlugin;
+pub use structure::Direction;
+pub use structure::Structure;
+pub use terrain::TerrainPlugin;
+pub use world::get_block;
+pub use world::get_ground_level;
+pub use world::set_block;
+pub use world::VoxelWorld;
+pub use world::VoxelWorldPlugin;
+pub use world::WorldEdit;
+
+use daynight::TimeOfDay;
+use fields::AirPollution;
+use fields::GroundPollution;
+use fields::Happiness;
+use fields::LandValue;
+use fields::NoiseLevel;
+use fields::ScalarField2D;
+use junction::Junctions;
+use network::RailNetwork;
+use network::RoadNetwork;
+use services::ServiceCoverage;
+use transit::TransitLines;
+
+pub const CHUNK_AXIS: usize = 32;
+
+/// Render-independent city simulation: the road and rail networks, transit
+/// lines, service coverage, the diffusing scalar fields and the clock. Runs
+/// the same with or without a window.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>()
+            .init_resource::<RoadNetwork>()
+            .init_resource::<Junctions>()
+            .init_resource::<RailNetwork>()
+            .init_resource::<TransitLines>()
+            .init_resource::<ServiceCoverage>()
+            .init_resource::<ScalarField2D<LandValue>>()
+            .init_resource::<ScalarField2D<Happiness>>()
+            .init_resource::<ScalarField2D<NoiseLevel>>()
+            .init_resource::<ScalarField2D<AirPollution>>()
+            .init_resource::<ScalarField2D<GroundPollution>>()
+            .add_systems(Update, services::update_coverage)
+            .add_systems(Update, daynight::advance_time_of_day)
+            .add_systems(
+                FixedUpdate,
+                (
+                    fields::step_field::<LandValue>,
+                    fields::step_field::<Happiness>,
+                    fields::step_field::<NoiseLevel>,
+                    fields::step_field::<AirPollution>,
+                    fields::step_field::<GroundPollution>,
+                    transit::step_transit,
+                ),
+            );
+    }
+}
@@ -0,0 +1,190 @@
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::render_resource::PrimitiveTopology;
+
+use crate::meshing::cube_mesh_parts;
+use crate::world::Active;
+use crate::world::Chunk;
+use crate::Block;
+use crate::Direction;
+use crate::Structure;
+use crate::VoxelWorld;
+use crate::CHUNK_AXIS;
+
+pub const LOD_DISTANCES: [i32; 3] = [2, 4, 8];
+const SKIRT_DEPTH: f32 = 2.0;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Lod(pub u32);
+
+impl Lod {
+    pub fn for_chunk(chunk: IVec3, origin: IVec3) -> Self {
+        let distance = (chunk - origin).xz().abs().max_element();
+        let level = LOD_DISTANCES
+            .iter()
+            .take_while(|&&limit| distance > limit)
+            .count();
+        Lod(level as u32)
+    }
+
+    pub fn scale(self) -> u32 {
+        1 << self.0
+    }
+}
+
+fn downsample(structure: &Structure, scale: u32) -> Vec<Block> {
+    let cells = CHUNK_AXIS as u32 / scale;
+    let range = (0..structure.count()).map(|i| structure.delinearize(i));
+    let blocks = structure.get_block(range).collect::<Vec<_>>();
+
+    let mut sampled = vec![Block::Air; (cells * cells * cells) as usize];
+    for cz in 0..cells {
+        for cy in 0..cells {
+            for cx in 0..cells {
+                let mut solid = 0;
+                let mut top = None;
+                for y in 0..scale {
+                    for z in 0..scale {
+                        for x in 0..scale {
+                            let position = UVec3::new(cx, cy, cz) * scale + UVec3::new(x, y, z);
+                            let block = blocks[structure.linearize(position)];
+                            if !matches!(block, Block::Air) {
+                                solid += 1;
+                                top = Some(block);
+                            }
+                        }
+                    }
+                }
+                if solid * 2 >= scale * scale * scale {
+                    sampled[((cz * cells + cy) * cells + cx) as usize] = top.unwrap();
+                }
+            }
+        }
+    }
+    sampled
+}
+
+fn skirt(
+    position: Vec3,
+    direction: Direction,
+    color: Vec4,
+    vertices: &mut Vec<[f32; 3]>,
+    colors: &mut Vec<[f32; 4]>,
+    normals: &mut Vec<[f32; 3]>,
+    indices: &mut Vec<u32>,
+) {
+    let (a, b, normal) = match direction {
+        Direction::LEFT => (Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.0, 1.0, 0.0), Vec3::NEG_X),
+        Direction::RIGHT => (Vec3::new(1.0, 1.0, 0.0), Vec3::new(1.0, 1.0, 1.0), Vec3::X),
+        Direction::BACK => (Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 1.0, 0.0), Vec3::NEG_Z),
+        Direction::FORWARD => (Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.0, 1.0, 1.0), Vec3::Z),
+        _ => return,
+    };
+    let count = vertices.len() as u32;
+    let down = Vec3::new(0.0, -SKIRT_DEPTH, 0.0);
+    for corner in [a, b, b + down, a + down] {
+        vertices.push((position + corner).to_array());
+        colors.push(color.to_array());
+        normals.push(normal.to_array());
+    }
+    indices.extend([0, 1, 2, 0, 2, 3].map(|i| count + i));
+}
+
+pub fn create_lod_mesh(structure: &Structure, lod: Lod) -> Mesh {
+    let scale = lod.scale();
+    let cells = (CHUNK_AXIS as u32 / scale) as i32;
+    let sampled = downsample(structure, scale);
+    let solid = |position: IVec3| -> Option<bool> {
+        if position.cmplt(IVec3::ZERO).any() || position.cmpge(IVec3::splat(cells)).any() {
+            None
+        } else {
+            let index = (position.z * cells + position.y) * cells + position.x;
+            Some(!matches!(sampled[index as usize], Block::Air))
+        }
+    };
+
+    let mut vertices = vec![];
+    let mut colors = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut indices = vec![];
+
+    let sides = [
+        (Direction::LEFT, IVec3::NEG_X),
+        (Direction::RIGHT, IVec3::X),
+        (Direction::DOWN, IVec3::NEG_Y),
+        (Direction::UP, IVec3::Y),
+        (Direction::BACK, IVec3::NEG_Z),
+        (Direction::FORWARD, IVec3::Z),
+    ];
+
+    for z in 0..cells {
+        for y in 0..cells {
+            for x in 0..cells {
+                let position = IVec3::new(x, y, z);
+                let block = sampled[((z * cells + y) * cells + x) as usize];
+                if matches!(block, Block::Air) {
+                    continue;
+                }
+                let surface = solid(position + IVec3::Y) != Some(true);
+                let mut directions = Direction::empty();
+                for (direction, normal) in sides {
+                    match solid(position + normal) {
+                        Some(false) => directions |= direction,
+                        None if direction == Direction::UP || direction == Direction::DOWN => {
+                            directions |= direction
+                        }
+                        None if surface => skirt(
+                            position.as_vec3(),
+                            direction,
+                            block.color(),
+                            &mut vertices,
+                            &mut colors,
+                            &mut normals,
+                            &mut indices,
+                        ),
+                        _ => {}
+                    }
+                }
+                uvs.resize(vertices.len(), [0.0, 0.0]);
+                cube_mesh_parts(
+                    position.as_vec3(),
+                    directions,
+                    block.color(),
+                    [Vec4::ONE; 6],
+                    [0.0; 6],
+                    &mut vertices,
+                    &mut colors,
+                    &mut normals,
+                    &mut uvs,
+                    &mut indices,
+                );
+            }
+        }
+    }
+    uvs.resize(vertices.len(), [0.0, 0.0]);
+
+    Mesh::new(PrimitiveTopology::TriangleList)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertices)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_indices(Some(Indices::U32(indices)))
+}
+
+pub fn update_lod(
+    mut commands: Commands,
+    mut origin: Local<Option<IVec3>>,
+    world: Res<VoxelWorld>,
+    chunks: Query<(Entity, &Chunk, &Lod), With<Active>>,
+) {
+    if *origin == Some(world.origin) {
+        return;
+    }
+    *origin = Some(world.origin);
+    for (entity, Chunk(position), lod) in chunks.iter() {
+        if Lod::for_chunk(*position, world.origin) != *lod {
+            commands.entity(entity).remove::<Active>();
+        }
+    }
+}
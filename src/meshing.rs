@@ -0,0 +1,271 @@
+use std::iter;
+
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::render_resource::PrimitiveTopology;
+
+use crate::lod;
+use crate::lod::Lod;
+use crate::overlay::ChunkMaterial;
+use crate::overlay::ChunkOverlay;
+use crate::overlay::OverlayPlugin;
+use crate::world::Active;
+use crate::world::Chunk;
+use crate::world::Dirty;
+use crate::Block;
+use crate::Direction;
+use crate::Structure;
+use crate::VoxelWorld;
+use crate::CHUNK_AXIS;
+
+/// Turns loaded chunks into meshes, picking a level of detail by distance
+/// from the load origin.
+pub struct MeshingPlugin;
+
+impl Plugin for MeshingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(OverlayPlugin)
+            .add_systems(Update, (lod::update_lod, mesh).chain());
+    }
+}
+
+pub(crate) fn cube_mesh_parts(
+    position: Vec3,
+    directions: Direction,
+    color: Vec4,
+    ao: [Vec4; 6],
+    emission: [f32; 6],
+    vertices: &mut Vec<[f32; 3]>,
+    colors: &mut Vec<[f32; 4]>,
+    normals: &mut Vec<[f32; 3]>,
+    uvs: &mut Vec<[f32; 2]>,
+    indices: &mut Vec<u32>,
+) {
+    let cube_vertices = [
+        [
+            [0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 1.0, 1.0],
+            [0.0, 1.0, 0.0],
+        ],
+        [
+            [1.0, 0.0, 0.0],
+            [1.0, 0.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [1.0, 1.0, 0.0],
+        ],
+        [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 0.0, 1.0],
+            [0.0, 0.0, 1.0],
+        ],
+        [
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [1.0, 1.0, 1.0],
+            [0.0, 1.0, 1.0],
+        ],
+        [
+            [0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+        ],
+        [
+            [0.0, 0.0, 1.0],
+            [0.0, 1.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [1.0, 0.0, 1.0],
+        ],
+    ];
+
+    let cube_normals = [
+        [
+            [-1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+        ],
+        [
+            [1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+        ],
+        [
+            [0.0, -1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, -1.0, 0.0],
+        ],
+        [
+            [0.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ],
+        [
+            [0.0, 0.0, -1.0],
+            [0.0, 0.0, -1.0],
+            [0.0, 0.0, -1.0],
+            [0.0, 0.0, -1.0],
+        ],
+        [
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, 1.0],
+        ],
+    ];
+
+    let cube_indices = [
+        [4, 5, 7, 5, 6, 7],
+        [0, 3, 1, 1, 3, 2],
+        [12, 13, 15, 13, 14, 15],
+        [8, 11, 9, 9, 11, 10],
+        [20, 21, 23, 21, 22, 23],
+        [16, 19, 17, 17, 19, 18],
+    ];
+
+    for current_direction in (0..6)
+        .map(|x| 1 << x)
+        .map(Direction::from_bits)
+        .map(Option::unwrap)
+    {
+        if current_direction & directions == Direction::empty() {
+            continue;
+        }
+        let index = current_direction.bits().trailing_zeros() as usize;
+
+        let count = vertices.len();
+
+        vertices.extend(
+            cube_vertices[index]
+                .iter()
+                .map(|unit| (Vec3::from_array(*unit) + position).to_array()),
+        );
+        let [a, b, c, d] = ao[index].to_array();
+        colors.push((color * Vec4::new(c, c, c, 1.0)).to_array());
+        colors.push((color * Vec4::new(b, b, b, 1.0)).to_array());
+        colors.push((color * Vec4::new(a, a, a, 1.0)).to_array());
+        colors.push((color * Vec4::new(d, d, d, 1.0)).to_array());
+        normals.extend(cube_normals[index].iter());
+        uvs.extend(iter::repeat([emission[index], 0.0]).take(4));
+        indices.extend(cube_indices[index].iter().map(|i| (count + i % 4) as u32))
+    }
+}
+
+#[rustfmt::skip]
+pub fn create_structure_mesh(structure: &Structure) -> Mesh {
+    let mut vertices = vec![];
+    let mut colors = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut indices = vec![];
+
+    let blocks = structure.get_block((0..structure.count()).map(|index| structure.delinearize(index))).collect::<Vec<_>>();
+    let cull = structure.get_cull((0..structure.count()).map(|index| structure.delinearize(index))).collect::<Vec<_>>();
+    let ao = structure.get_ao((0..structure.count()).map(|index| structure.delinearize(index))).collect::<Vec<_>>();
+    for index in 0..structure.count() {
+        let position = structure.delinearize(index);
+        if !matches!(blocks[index], Block::Air) {
+            cube_mesh_parts(position.as_vec3(), cull[index], blocks[index].color(), ao[index], blocks[index].emission(position), &mut vertices, &mut colors, &mut normals, &mut uvs, &mut indices);
+        }
+    }
+
+    Mesh::new(PrimitiveTopology::TriangleList)
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+            vertices
+    )
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_COLOR,
+            colors
+    )
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_NORMAL,
+        normals
+    )
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_UV_0,
+        uvs
+    )
+    .with_indices(Some(Indices::U32(indices)))
+}
+
+fn mesh(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    world: Res<VoxelWorld>,
+    query: Query<(Entity, &Structure, &Chunk, Option<&ChunkOverlay>, Has<Dirty>), Without<Active>>,
+) {
+    for (entity, structure, Chunk(position), chunk_overlay, dirty) in query.iter() {
+        let lod = Lod::for_chunk(*position, world.origin);
+        let hidden = match structure.uniform_block() {
+            Some(Block::Air) => true,
+            // Faces on the border of a solid chunk are known once it is
+            // consolidated.
+            Some(_) if dirty => continue,
+            Some(_) => !structure.has_visible_faces(),
+            None => false,
+        };
+        if hidden {
+            // Edits can empty or fill a meshed chunk; drop its old geometry.
+            commands
+                .entity(entity)
+                .insert((Active, lod))
+                .remove::<Handle<Mesh>>();
+            continue;
+        }
+        if lod.0 == 0 && dirty {
+            continue;
+        }
+        let structure_mesh = if lod.0 == 0 {
+            create_structure_mesh(&structure)
+        } else {
+            lod::create_lod_mesh(&structure, lod)
+        };
+        let cube_mesh_handle: Handle<Mesh> = meshes.add(structure_mesh);
+        let material = match chunk_overlay {
+            Some(chunk_overlay) => chunk_overlay.material.clone(),
+            None => {
+                let chunk_overlay = ChunkOverlay::new(
+                    *position,
+                    StandardMaterial {
+                        base_color: Color::Rgba {
+                            red: 1.0,
+                            green: 1.0,
+                            blue: 1.0,
+                            alpha: 1.0,
+                        },
+                        metallic: 0.0,
+                        reflectance: 0.1,
+                        ..default()
+                    },
+                    &mut images,
+                    &mut materials,
+                );
+                let material = chunk_overlay.material.clone();
+                commands.entity(entity).insert(chunk_overlay);
+                material
+            }
+        };
+        commands.entity(entity).insert((
+            Active,
+            lod,
+            MaterialMeshBundle::<ChunkMaterial> {
+                mesh: cube_mesh_handle,
+                material,
+                transform: Transform {
+                    translation: position.as_vec3() * CHUNK_AXIS as f32,
+                    scale: Vec3::splat(lod.scale() as f32),
+                    ..default()
+                },
+                ..default()
+            },
+        ));
+    }
+}
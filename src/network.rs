@@ -0,0 +1,175 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+use bevy::utils::hashbrown::HashMap;
+
+pub const STRAIGHT_COST: u32 = 10;
+pub const DIAGONAL_COST: u32 = 14;
+
+/// Placement limits of a kind of transport network.
+pub trait NetworkKind: Send + Sync + 'static {
+    const NAME: &'static str;
+    /// Steepest rise per horizontal voxel.
+    const MAX_GRADE: f32;
+    /// Tightest curve, as a radius in voxels.
+    const MIN_RADIUS: f32;
+}
+
+pub struct Road;
+pub struct Rail;
+
+impl NetworkKind for Road {
+    const NAME: &'static str = "road";
+    const MAX_GRADE: f32 = 0.12;
+    const MIN_RADIUS: f32 = 6.0;
+}
+
+impl NetworkKind for Rail {
+    const NAME: &'static str = "railway";
+    const MAX_GRADE: f32 = 0.04;
+    const MIN_RADIUS: f32 = 24.0;
+}
+
+pub type RoadNetwork = Network<Road>;
+pub type RailNetwork = Network<Rail>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SegmentId(pub u32);
+
+/// Columns covered by a network, with the surface height of each, and the
+/// centerlines of the segments that were built to form it.
+#[derive(Resource)]
+pub struct Network<K: NetworkKind> {
+    cells: HashMap<IVec2, i32>,
+    owners: HashMap<IVec2, SegmentId>,
+    segments: Vec<Vec<IVec2>>,
+    kind: PhantomData<K>,
+}
+
+impl<K: NetworkKind> Default for Network<K> {
+    fn default() -> Self {
+        Network {
+            cells: HashMap::new(),
+            owners: HashMap::new(),
+            segments: vec![],
+            kind: PhantomData,
+        }
+    }
+}
+
+impl<K: NetworkKind> Network<K> {
+    pub fn insert(&mut self, position: IVec3) -> bool {
+        self.cells.insert(position.xz(), position.y).is_none()
+    }
+
+    pub fn contains(&self, column: IVec2) -> bool {
+        self.cells.contains_key(&column)
+    }
+
+    pub fn height(&self, column: IVec2) -> Option<i32> {
+        self.cells.get(&column).copied()
+    }
+
+    /// Records the centerline of a newly built segment. Columns already owned
+    /// by another segment keep their owner.
+    pub fn add_segment(&mut self, path: Vec<IVec2>) -> SegmentId {
+        let id = SegmentId(self.segments.len() as u32);
+        for &column in &path {
+            self.owners.entry(column).or_insert(id);
+        }
+        self.segments.push(path);
+        id
+    }
+
+    pub fn segment_at(&self, column: IVec2) -> Option<SegmentId> {
+        self.owners.get(&column).copied()
+    }
+
+    pub fn segment(&self, id: SegmentId) -> &[IVec2] {
+        &self.segments[id.0 as usize]
+    }
+
+    /// The network column closest to `column` within Chebyshev `radius`.
+    pub fn nearest(&self, column: IVec2, radius: i32) -> Option<IVec2> {
+        (-radius..=radius)
+            .flat_map(|z| (-radius..=radius).map(move |x| column + IVec2::new(x, z)))
+            .filter(|candidate| self.contains(*candidate))
+            .min_by_key(|candidate| (*candidate - column).length_squared())
+    }
+
+    pub(crate) fn neighbors(&self, column: IVec2) -> impl Iterator<Item = (IVec2, u32)> + '_ {
+        (-1..=1)
+            .flat_map(|x| (-1..=1).map(move |z| IVec2::new(x, z)))
+            .filter(|offset| *offset != IVec2::ZERO)
+            .map(move |offset| {
+                let cost = if offset.x != 0 && offset.y != 0 {
+                    DIAGONAL_COST
+                } else {
+                    STRAIGHT_COST
+                };
+                (column + offset, cost)
+            })
+            .filter(|(neighbor, _)| self.cells.contains_key(neighbor))
+    }
+
+    /// Shortest path over the network from `from` to `to`, both included.
+    pub fn route(&self, from: IVec2, to: IVec2) -> Option<Vec<IVec2>> {
+        self.route_with(from, to, |(), _, _| Some(()))
+    }
+
+    /// Shortest path like `route`, carrying some state along it: `step`
+    /// returns the state after moving from a column to its neighbor, or
+    /// `None` where that move is not allowed. Paths start in the default
+    /// state.
+    pub fn route_with<S: Copy + Eq + Hash + Ord + Default>(
+        &self,
+        from: IVec2,
+        to: IVec2,
+        mut step: impl FnMut(S, IVec2, IVec2) -> Option<S>,
+    ) -> Option<Vec<IVec2>> {
+        if !self.contains(from) || !self.contains(to) {
+            return None;
+        }
+        let start = (from.to_array(), S::default());
+        let mut distances = HashMap::new();
+        let mut parents = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        let mut end = None;
+        distances.insert(start, 0);
+        queue.push(Reverse((0, start)));
+        while let Some(Reverse((distance, node))) = queue.pop() {
+            let column = IVec2::from_array(node.0);
+            if column == to {
+                end = Some(node);
+                break;
+            }
+            if distances.get(&node).is_some_and(|&best| best < distance) {
+                continue;
+            }
+            for (neighbor, cost) in self.neighbors(column) {
+                let Some(state) = step(node.1, column, neighbor) else {
+                    continue;
+                };
+                let next_node = (neighbor.to_array(), state);
+                let next = distance + cost;
+                if distances.get(&next_node).map_or(true, |&best| next < best) {
+                    distances.insert(next_node, next);
+                    parents.insert(next_node, node);
+                    queue.push(Reverse((next, next_node)));
+                }
+            }
+        }
+
+        let mut node = end?;
+        let mut path = vec![to];
+        while node != start {
+            node = parents[&node];
+            path.push(IVec2::from_array(node.0));
+        }
+        path.reverse();
+        Some(path)
+    }
+}
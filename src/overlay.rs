@@ -0,0 +1,204 @@
+use bevy::asset::load_internal_asset;
+use bevy::ecs::system::SystemParam;
+use bevy::pbr::ExtendedMaterial;
+use bevy::pbr::MaterialExtension;
+use bevy::prelude::*;
+use bevy::render::render_resource::AsBindGroup;
+use bevy::render::render_resource::Extent3d;
+use bevy::render::render_resource::ShaderRef;
+use bevy::render::render_resource::TextureDimension;
+use bevy::render::render_resource::TextureFormat;
+use bevy::render::texture::ImageSampler;
+
+use crate::fields::heatmap;
+use crate::fields::AirPollution;
+use crate::fields::FieldLayer;
+use crate::fields::GroundPollution;
+use crate::fields::Happiness;
+use crate::fields::LandValue;
+use crate::fields::NoiseLevel;
+use crate::fields::ScalarField2D;
+use crate::services::coverage_tint;
+use crate::services::ServiceCoverage;
+use crate::services::ServiceKind;
+use crate::world::Chunk;
+use crate::CHUNK_AXIS;
+
+const FIELD_REFRESH_SECONDS: f32 = 1.0;
+const OVERLAY_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(0x5c1e_0f3a_9d27_4b8e_a6c1_72e4_d08b_3f95);
+
+pub type ChunkMaterial = ExtendedMaterial<StandardMaterial, OverlayExtension>;
+
+#[derive(Asset, AsBindGroup, TypePath, Debug, Clone)]
+pub struct OverlayExtension {
+    #[uniform(100)]
+    settings: Vec4,
+    #[texture(101)]
+    #[sampler(102)]
+    texture: Handle<Image>,
+    #[uniform(103)]
+    lighting: Vec4,
+}
+
+impl MaterialExtension for OverlayExtension {
+    fn fragment_shader() -> ShaderRef {
+        OVERLAY_SHADER_HANDLE.into()
+    }
+}
+
+#[derive(Component)]
+pub struct ChunkOverlay {
+    image: Handle<Image>,
+    pub material: Handle<ChunkMaterial>,
+}
+
+impl ChunkOverlay {
+    pub fn new(
+        chunk_position: IVec3,
+        base: StandardMaterial,
+        images: &mut Assets<Image>,
+        materials: &mut Assets<ChunkMaterial>,
+    ) -> Self {
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: CHUNK_AXIS as u32,
+                height: CHUNK_AXIS as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[255, 255, 255, 0],
+            TextureFormat::Rgba8Unorm,
+        );
+        image.sampler = ImageSampler::nearest();
+        let image = images.add(image);
+        let origin = chunk_position.xz().as_vec2() * CHUNK_AXIS as f32;
+        let material = materials.add(ExtendedMaterial {
+            base,
+            extension: OverlayExtension {
+                settings: Vec4::new(origin.x, origin.y, 1.0, CHUNK_AXIS as f32),
+                texture: image.clone(),
+                lighting: Vec4::ZERO,
+            },
+        });
+        ChunkOverlay { image, material }
+    }
+
+    pub fn set_night(&self, materials: &mut Assets<ChunkMaterial>, night: f32) {
+        if let Some(material) = materials.get_mut(&self.material) {
+            material.extension.lighting.x = night;
+        }
+    }
+}
+
+#[derive(Resource, Default, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Overlay {
+    #[default]
+    None,
+    Coverage(ServiceKind),
+    Field(FieldLayer),
+}
+
+impl Overlay {
+    fn cycle(self) -> Self {
+        let all = iter_overlays().collect::<Vec<_>>();
+        let index = all.iter().position(|&overlay| overlay == self).unwrap_or(0);
+        all[(index + 1) % all.len()]
+    }
+}
+
+fn iter_overlays() -> impl Iterator<Item = Overlay> {
+    std::iter::once(Overlay::None)
+        .chain(ServiceKind::ALL.into_iter().map(Overlay::Coverage))
+        .chain(FieldLayer::ALL.into_iter().map(Overlay::Field))
+}
+
+#[derive(SystemParam)]
+pub struct OverlaySource<'w> {
+    pub overlay: Res<'w, Overlay>,
+    coverage: Res<'w, ServiceCoverage>,
+    land_value: Res<'w, ScalarField2D<LandValue>>,
+    happiness: Res<'w, ScalarField2D<Happiness>>,
+    noise: Res<'w, ScalarField2D<NoiseLevel>>,
+    air: Res<'w, ScalarField2D<AirPollution>>,
+    ground: Res<'w, ScalarField2D<GroundPollution>>,
+}
+
+impl<'w> OverlaySource<'w> {
+    pub fn tint(&self, column: IVec2) -> Option<Vec4> {
+        match *self.overlay {
+            Overlay::None => None,
+            Overlay::Coverage(kind) => Some(coverage_tint(self.coverage.level(kind, column))),
+            Overlay::Field(layer) => Some(heatmap(match layer {
+                FieldLayer::LandValue => self.land_value.normalized(column),
+                FieldLayer::Happiness => self.happiness.normalized(column),
+                FieldLayer::Noise => self.noise.normalized(column),
+                FieldLayer::AirPollution => self.air.normalized(column),
+                FieldLayer::GroundPollution => self.ground.normalized(column),
+            })),
+        }
+    }
+
+    fn write(&self, image: &mut Image, chunk_position: IVec3) {
+        let origin = chunk_position.xz() * CHUNK_AXIS as i32;
+        for z in 0..CHUNK_AXIS {
+            for x in 0..CHUNK_AXIS {
+                let texel = match self.tint(origin + IVec2::new(x as i32, z as i32)) {
+                    Some(tint) => {
+                        let [r, g, b, _] = (tint * 255.0).to_array();
+                        [r as u8, g as u8, b as u8, 255]
+                    }
+                    None => [255, 255, 255, 0],
+                };
+                let index = (z * CHUNK_AXIS + x) * 4;
+                image.data[index..index + 4].copy_from_slice(&texel);
+            }
+        }
+    }
+}
+
+pub fn overlay_input(keys: Res<Input<KeyCode>>, mut overlay: ResMut<Overlay>) {
+    if keys.just_pressed(KeyCode::O) {
+        *overlay = overlay.cycle();
+    }
+}
+
+pub fn refresh_overlay(
+    mut elapsed: Local<f32>,
+    time: Res<Time>,
+    source: OverlaySource,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    chunks: Query<(&Chunk, Ref<ChunkOverlay>)>,
+) {
+    *elapsed += time.delta_seconds();
+    let stale = source.overlay.is_changed()
+        || match *source.overlay {
+            Overlay::None => false,
+            Overlay::Coverage(_) => source.coverage.is_changed(),
+            Overlay::Field(_) => *elapsed >= FIELD_REFRESH_SECONDS,
+        };
+    if stale {
+        *elapsed = 0.0;
+    }
+    for (Chunk(position), chunk_overlay) in chunks.iter() {
+        if !stale && !chunk_overlay.is_added() {
+            continue;
+        }
+        if let Some(image) = images.get_mut(&chunk_overlay.image) {
+            source.write(image, *position);
+        }
+        // Marks the material changed so the overlay texture is re-uploaded.
+        let _ = materials.get_mut(&chunk_overlay.material);
+    }
+}
+
+pub struct OverlayPlugin;
+
+impl Plugin for OverlayPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(app, OVERLAY_SHADER_HANDLE, "overlay.wgsl", Shader::from_wgsl);
+        app.add_plugins(MaterialPlugin::<ChunkMaterial>::default())
+            .init_resource::<Overlay>()
+            .add_systems(Update, (overlay_input, refresh_overlay));
+    }
+}
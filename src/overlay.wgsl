@@ -0,0 +1,60 @@
+#import bevy_pbr::{
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::alpha_discard,
+}
+
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::{
+    prepass_io::{VertexOutput, FragmentOutput},
+    pbr_deferred_functions::deferred_output,
+}
+#else
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+#endif
+
+// xy: chunk origin in world xz, z: overlay strength, w: chunk axis
+@group(1) @binding(100)
+var<uniform> overlay_settings: vec4<f32>;
+@group(1) @binding(101)
+var overlay_texture: texture_2d<f32>;
+@group(1) @binding(102)
+var overlay_sampler: sampler;
+// x: night factor used for window emission
+@group(1) @binding(103)
+var<uniform> lighting: vec4<f32>;
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+
+    let column = in.world_position.xz - in.world_normal.xz * 0.5 - overlay_settings.xy;
+    let tint = textureSample(overlay_texture, overlay_sampler, column / overlay_settings.w);
+    let strength = overlay_settings.z * tint.a;
+    pbr_input.material.base_color = vec4(
+        pbr_input.material.base_color.rgb * mix(vec3(1.0), tint.rgb, strength),
+        pbr_input.material.base_color.a,
+    );
+
+#ifdef VERTEX_UVS
+    let window = in.uv.x * lighting.x;
+    pbr_input.material.emissive = vec4(vec3(1.0, 0.78, 0.45) * window * 4.0, 1.0);
+#endif
+
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+#ifdef PREPASS_PIPELINE
+    let out = deferred_output(in, pbr_input);
+#else
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+#endif
+
+    return out;
+}
@@ -0,0 +1,144 @@
+#[derive(Clone, Debug)]
+pub struct PalettedChannel {
+    len: usize,
+    palette: Vec<u64>,
+    bits: u32,
+    words: Vec<u64>,
+}
+
+impl PalettedChannel {
+    pub fn new(len: usize, value: u64) -> Self {
+        PalettedChannel {
+            len,
+            palette: vec![value],
+            bits: 0,
+            words: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn uniform(&self) -> Option<u64> {
+        if self.bits == 0 {
+            Some(self.palette[0])
+        } else {
+            None
+        }
+    }
+
+    pub fn bits_per_entry(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn palette(&self) -> &[u64] {
+        &self.palette
+    }
+
+    pub fn memory(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.palette.capacity() * std::mem::size_of::<u64>()
+            + self.words.capacity() * std::mem::size_of::<u64>()
+    }
+
+    fn entries_per_word(bits: u32) -> usize {
+        (64 / bits) as usize
+    }
+
+    fn bits_for(palette_len: usize) -> u32 {
+        if palette_len <= 1 {
+            0
+        } else {
+            usize::BITS - (palette_len - 1).leading_zeros()
+        }
+    }
+
+    fn read(&self, index: usize) -> usize {
+        let per_word = Self::entries_per_word(self.bits);
+        let word = self.words[index / per_word];
+        let shift = (index % per_word) as u32 * self.bits;
+        ((word >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+
+    fn write(&mut self, index: usize, id: usize) {
+        let per_word = Self::entries_per_word(self.bits);
+        let word = &mut self.words[index / per_word];
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        *word = (*word & !mask) | ((id as u64) << shift);
+    }
+
+    fn repack(&mut self, bits: u32, remap: impl Fn(usize) -> usize) {
+        let ids = (0..self.len)
+            .map(|index| if self.bits == 0 { 0 } else { self.read(index) })
+            .collect::<Vec<_>>();
+        self.bits = bits;
+        if bits == 0 {
+            self.words = vec![];
+            return;
+        }
+        self.words = vec![0; (self.len + Self::entries_per_word(bits) - 1) / Self::entries_per_word(bits)];
+        for (index, id) in ids.into_iter().enumerate() {
+            self.write(index, remap(id));
+        }
+    }
+
+    fn palette_id(&mut self, value: u64) -> usize {
+        if let Some(id) = self.palette.iter().position(|&entry| entry == value) {
+            return id;
+        }
+        self.palette.push(value);
+        let bits = Self::bits_for(self.palette.len());
+        if bits != self.bits {
+            self.repack(bits, |id| id);
+        }
+        self.palette.len() - 1
+    }
+
+    pub fn get(&self, index: usize) -> u64 {
+        if self.bits == 0 {
+            self.palette[0]
+        } else {
+            self.palette[self.read(index)]
+        }
+    }
+
+    pub fn set(&mut self, index: usize, value: u64) {
+        if self.bits == 0 && self.palette[0] == value {
+            return;
+        }
+        let id = self.palette_id(value);
+        self.write(index, id);
+    }
+
+    pub fn set_many(&mut self, data: impl IntoIterator<Item = (usize, u64)>) {
+        for (index, value) in data {
+            self.set(index, value);
+        }
+    }
+
+    pub fn compact(&mut self) {
+        if self.bits == 0 {
+            return;
+        }
+        let mut used = vec![false; self.palette.len()];
+        for index in 0..self.len {
+            used[self.read(index)] = true;
+        }
+        if used.iter().all(|&used| used) {
+            return;
+        }
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = vec![];
+        for (id, &value) in self.palette.iter().enumerate() {
+            if used[id] {
+                remap[id] = palette.len();
+                palette.push(value);
+            }
+        }
+        let bits = Self::bits_for(palette.len());
+        self.repack(bits, |id| remap[id]);
+        self.palette = palette;
+    }
+}
@@ -0,0 +1,167 @@
+use std::iter;
+
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::Block;
+use crate::Direction;
+use crate::Structure;
+use crate::VoxelWorld;
+use crate::CHUNK_AXIS;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct VoxelHit {
+    pub voxel: IVec3,
+    pub face: Direction,
+    pub normal: IVec3,
+    pub distance: f32,
+    pub chunk: IVec3,
+}
+
+impl VoxelHit {
+    pub fn adjacent(&self) -> IVec3 {
+        self.voxel + self.normal
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct CursorHit(pub Option<VoxelHit>);
+
+pub trait RayExt {
+    fn intersect_voxels(self, bevy_world: &bevy::prelude::World) -> Option<VoxelHit>;
+}
+
+pub struct VoxelRay {
+    position: IVec3,
+    mask: BVec3,
+    fmask: Vec3,
+    imask: IVec3,
+    side_dist: Vec3,
+    delta_dist: Vec3,
+    ray_step: IVec3,
+    distance: f32,
+    step_count: usize,
+}
+
+fn voxel_ray(ray: Ray) -> VoxelRay {
+    let mut position = ray.origin.floor().as_ivec3();
+    let mut mask = BVec3::splat(false);
+    let delta_dist = 1.0 / ray.direction.abs();
+    let mut side_dist = ray.direction.signum()
+        * ((ray.origin.floor() - ray.origin) + (ray.direction.signum() * 0.5) + 0.5)
+        * delta_dist;
+    let ray_step = ray.direction.signum().as_ivec3();
+    let fmask = default();
+    let imask = default();
+    let distance = default();
+    let step_count = default();
+    VoxelRay {
+        position,
+        mask,
+        side_dist,
+        delta_dist,
+        ray_step,
+        fmask,
+        imask,
+        distance,
+        step_count,
+    }
+}
+
+fn voxel_step(ray: &mut VoxelRay) {
+    let VoxelRay {
+        position,
+        mask,
+        side_dist,
+        delta_dist,
+        ray_step,
+        fmask,
+        imask,
+        distance,
+        step_count,
+    } = ray;
+    // Step along exactly one axis per voxel; ties go to x, then y, then z,
+    // so a ray through an edge or corner still crosses a single face.
+    mask.x = side_dist.x <= side_dist.y.min(side_dist.z);
+    mask.y = !mask.x && side_dist.y <= side_dist.z;
+    mask.z = !mask.x && !mask.y;
+
+    *imask = IVec3::new(mask.x as i32, mask.y as i32, mask.z as i32);
+    *fmask = Vec3::new(imask.x as f32, imask.y as f32, imask.z as f32);
+
+    *side_dist += *fmask * *delta_dist;
+    *position += *imask * *ray_step;
+    *distance = (*fmask * (*side_dist - *delta_dist)).length();
+    *step_count += 1;
+}
+
+impl RayExt for bevy::math::Ray {
+    fn intersect_voxels(mut self, bevy_world: &bevy::prelude::World) -> Option<VoxelHit> {
+        self.direction = self.direction.normalize();
+        let mut ray = voxel_ray(self);
+
+        loop {
+            if ray.step_count >= 8192 {
+                return None;
+            }
+
+            let chunk_position = ray.position.div_euclid(IVec3::splat(CHUNK_AXIS as i32));
+
+            if let Some(&chunk_entity) = bevy_world.resource::<VoxelWorld>().mapping.get(&chunk_position)
+            {
+                let chunk = bevy_world.get::<Structure>(chunk_entity).unwrap();
+
+                let local_position = ray
+                    .position
+                    .rem_euclid(IVec3::splat(CHUNK_AXIS as i32))
+                    .as_uvec3();
+
+                if !matches!(
+                    chunk.get_block(iter::once(local_position)).next().unwrap(),
+                    Block::Air
+                ) {
+                    let normal = if ray.step_count == 0 {
+                        let extent = self.direction.abs();
+                        let axis = extent.max_element();
+                        let dominant = if extent.x == axis {
+                            IVec3::X
+                        } else if extent.y == axis {
+                            IVec3::Y
+                        } else {
+                            IVec3::Z
+                        };
+                        -dominant * ray.ray_step
+                    } else {
+                        -ray.imask * ray.ray_step
+                    };
+                    return Some(VoxelHit {
+                        voxel: ray.position,
+                        face: Direction::from_normal(normal),
+                        normal,
+                        distance: ray.distance,
+                        chunk: chunk_position,
+                    });
+                }
+            }
+            voxel_step(&mut ray);
+        }
+    }
+}
+
+pub(crate) fn cursor_system(bevy_world: &mut bevy::prelude::World) {
+    let viewport_position = {
+        let mut system_state =
+            SystemState::<Query<&Window, With<PrimaryWindow>>>::new(bevy_world);
+        let query = system_state.get(bevy_world);
+        query.get_single().ok().and_then(Window::cursor_position)
+    };
+    let ray = viewport_position.and_then(|viewport_position| {
+        let mut system_state = SystemState::<Query<(&GlobalTransform, &Camera)>>::new(bevy_world);
+        let query = system_state.get(bevy_world);
+        let (transform, camera) = query.get_single().ok()?;
+        camera.viewport_to_world(transform, viewport_position)
+    });
+    let hit = ray.and_then(|ray| ray.intersect_voxels(bevy_world));
+    bevy_world.resource_mut::<CursorHit>().0 = hit;
+}
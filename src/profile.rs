@@ -0,0 +1,188 @@
+use bevy::prelude::*;
+
+use crate::grading::ProfileSample;
+use crate::road::draw_line;
+use crate::road::LineMode;
+use crate::Block;
+
+/// Samples on each side used to estimate the road direction at a sample.
+const TANGENT_REACH: usize = 2;
+/// Length of a lane divider dash, and of the gap after it.
+const DASH_LENGTH: f32 = 3.0;
+/// Distance between sleepers, and the ballast gap between them.
+const SLEEPER_SPACING: f32 = 1.0;
+
+/// Cross-section of a road: carriageway lanes, markings, curbs and sidewalks.
+#[derive(Clone, Copy, Debug)]
+pub struct RoadProfile {
+    /// Lanes in each direction.
+    pub lanes: i32,
+    pub lane_width: i32,
+    pub sidewalk_width: i32,
+}
+
+impl Default for RoadProfile {
+    fn default() -> Self {
+        RoadProfile {
+            lanes: 1,
+            lane_width: 3,
+            sidewalk_width: 2,
+        }
+    }
+}
+
+/// A line of blocks running parallel to the centerline.
+struct Strip {
+    offset: f32,
+    block: Block,
+    /// Placed one voxel above the road surface, on a stone footing.
+    raised: bool,
+    /// Length of the painted dashes and of the gaps between them; zero for a
+    /// solid line.
+    dash: f32,
+}
+
+impl Strip {
+    fn new(offset: f32, block: Block) -> Self {
+        Strip {
+            offset,
+            block,
+            raised: false,
+            dash: 0.0,
+        }
+    }
+}
+
+/// Offsets from `from` to `to` at half voxel spacing, which keeps diagonal
+/// stretches free of holes.
+fn fill(from: f32, to: f32) -> impl Iterator<Item = f32> {
+    let count = ((to - from) * 2.0) as i32;
+    (0..=count).map(move |i| from + i as f32 * 0.5)
+}
+
+/// Paints `strips` in order along the graded centerline. Solid strips are
+/// drawn as face-connected offset lines between consecutive samples, dashed
+/// ones per sample.
+fn place_strips(strips: &[Strip], samples: &[ProfileSample], mut f: impl FnMut(IVec3, Block)) {
+    let n = samples.len();
+    let normals = (0..n)
+        .map(|i| {
+            let before = samples[i.saturating_sub(TANGENT_REACH)].deck.xz();
+            let after = samples[(i + TANGENT_REACH).min(n - 1)].deck.xz();
+            let tangent = (after - before).as_vec2().normalize_or_zero();
+            Vec2::new(-tangent.y, tangent.x)
+        })
+        .collect::<Vec<_>>();
+
+    for strip in strips {
+        let mut paint = |pos: IVec3| {
+            (f)(pos, strip.block);
+            if strip.raised {
+                (f)(pos - IVec3::Y, Block::Stone);
+            }
+        };
+        let mut previous = None;
+        for (sample, normal) in samples.iter().zip(&normals) {
+            let shift = (*normal * strip.offset).round().as_ivec2();
+            let point = sample.deck + IVec3::new(shift.x, strip.raised as i32, shift.y);
+            if strip.dash == 0.0 {
+                draw_line(previous.unwrap_or(point), point, LineMode::BOTH, &mut paint);
+            } else if (sample.distance / strip.dash) as i32 % 2 == 0 {
+                (paint)(point);
+            }
+            previous = Some(point);
+        }
+    }
+}
+
+impl RoadProfile {
+    /// Distance from the centerline to the edge line.
+    pub fn carriageway(&self) -> i32 {
+        self.lanes * self.lane_width
+    }
+
+    /// Distance from the centerline to the outer edge of the sidewalk.
+    pub fn half_width(&self) -> i32 {
+        self.carriageway() + 1 + self.sidewalk_width
+    }
+
+    /// Strips in placement order: later strips paint over earlier ones.
+    fn strips(&self) -> Vec<Strip> {
+        let carriageway = self.carriageway() as f32;
+        let mut strips = fill(-carriageway, carriageway)
+            .map(|offset| Strip::new(offset, Block::Asphalt))
+            .collect::<Vec<_>>();
+        for side in [-1.0, 1.0] {
+            for offset in fill(carriageway + 2.0, self.half_width() as f32) {
+                strips.push(Strip {
+                    raised: true,
+                    ..Strip::new(side * offset, Block::Sidewalk)
+                });
+            }
+            strips.push(Strip {
+                raised: true,
+                ..Strip::new(side * (carriageway + 1.0), Block::Curb)
+            });
+            for lane in 1..self.lanes {
+                strips.push(Strip {
+                    dash: DASH_LENGTH,
+                    ..Strip::new(side * (lane * self.lane_width) as f32, Block::LaneMarking)
+                });
+            }
+            strips.push(Strip::new(side * carriageway, Block::LaneMarking));
+        }
+        strips.push(Strip::new(0.0, Block::Centerline));
+        strips
+    }
+
+    /// Paints the full cross-section along the graded centerline.
+    pub fn place(&self, samples: &[ProfileSample], f: impl FnMut(IVec3, Block)) {
+        place_strips(&self.strips(), samples, f);
+    }
+}
+
+/// Cross-section of a railway: ballast bed, sleepers and a pair of rails.
+#[derive(Clone, Copy, Debug)]
+pub struct RailProfile {
+    /// Distance from the centerline to each rail.
+    pub half_gauge: i32,
+    /// Ballast beyond the ends of the sleepers.
+    pub shoulder: i32,
+}
+
+impl Default for RailProfile {
+    fn default() -> Self {
+        RailProfile {
+            half_gauge: 1,
+            shoulder: 1,
+        }
+    }
+}
+
+impl RailProfile {
+    pub fn half_width(&self) -> i32 {
+        self.half_gauge + 1 + self.shoulder
+    }
+
+    fn strips(&self) -> Vec<Strip> {
+        let half_width = self.half_width() as f32;
+        let sleeper = (self.half_gauge + 1) as f32;
+        let mut strips = fill(-half_width, half_width)
+            .map(|offset| Strip::new(offset, Block::Ballast))
+            .collect::<Vec<_>>();
+        for offset in fill(-sleeper, sleeper) {
+            strips.push(Strip {
+                dash: SLEEPER_SPACING,
+                ..Strip::new(offset, Block::Sleeper)
+            });
+        }
+        for side in [-1.0, 1.0] {
+            strips.push(Strip::new(side * self.half_gauge as f32, Block::Rail));
+        }
+        strips
+    }
+
+    pub fn place(&self, samples: &[ProfileSample], f: impl FnMut(IVec3, Block)) {
+        place_strips(&self.strips(), samples, f);
+    }
+}
@@ -0,0 +1,453 @@
+use bevy::prelude::*;
+use bitflags::bitflags;
+
+use crate::block_tool;
+use crate::block_tool::BlockTool;
+use crate::curve;
+use crate::curve::CurveShape;
+use crate::fields;
+use crate::fields::AirPollution;
+use crate::fields::LandValue;
+use crate::fields::NoiseLevel;
+use crate::fields::ScalarField2D;
+use crate::grading;
+use crate::grading::RoadPlan;
+use crate::junction;
+use crate::junction::Junctions;
+use crate::network::NetworkKind;
+use crate::network::Rail;
+use crate::network::RailNetwork;
+use crate::network::Road;
+use crate::network::RoadNetwork;
+use crate::picking::cursor_system;
+use crate::profile::RailProfile;
+use crate::profile::RoadProfile;
+use crate::services;
+use crate::services::ServiceTool;
+use crate::transit;
+use crate::transit::TransitTool;
+use crate::validation;
+use crate::validation::CurveReport;
+use crate::validation::Limits;
+use crate::Block;
+use crate::CursorHit;
+use crate::WorldEdit;
+
+/// Cursor picking and the interactive editing tools: roads and railways,
+/// transit lines, service buildings and freeform block placement.
+pub struct RoadToolPlugin;
+
+impl Plugin for RoadToolPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BuildTool>()
+            .init_resource::<CursorHit>()
+            .init_resource::<BlockTool>()
+            .init_resource::<ServiceTool>()
+            .init_resource::<TransitTool>()
+            .add_systems(Update, (cursor_system, cast_system).chain())
+            .add_systems(
+                Update,
+                (block_tool::block_tool_input, block_tool::block_tool_gizmo),
+            )
+            .add_systems(Update, (road_tool_input, junction::junction_input))
+            .add_systems(Update, (build_road, build_tool_gizmo))
+            .add_systems(Update, services::service_tool_input)
+            .add_systems(
+                Update,
+                (transit::transit_tool_input, transit::open_line).chain(),
+            );
+    }
+}
+
+/// R toggles whether new junctions are built as roundabouts, N switches
+/// between laying roads and railways and C cycles the curve shape. Splines
+/// are finished with Enter.
+fn road_tool_input(keys: Res<Input<KeyCode>>, mut tool: ResMut<BuildTool>) {
+    if keys.just_pressed(KeyCode::C) {
+        tool.shape = tool.shape.next();
+        tool.points.clear();
+        info!("curve shape: {:?}", tool.shape);
+    }
+    if keys.just_pressed(KeyCode::Return) && tool.shape == CurveShape::Spline {
+        tool.finish = true;
+    }
+    if keys.just_pressed(KeyCode::R) {
+        tool.roundabouts = !tool.roundabouts;
+        info!("roundabouts: {}", tool.roundabouts);
+    }
+    if keys.just_pressed(KeyCode::N) {
+        tool.track = match tool.track {
+            Track::Road => Track::Rail,
+            Track::Rail => Track::Road,
+        };
+        info!("laying {:?}", tool.track);
+    }
+}
+
+fn cast_system(bevy_world: &mut bevy::prelude::World) {
+    if block_tool::apply_block_tool(bevy_world) {
+        return;
+    }
+    let mouse = bevy_world.resource::<Input<MouseButton>>();
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(hit) = bevy_world.resource::<CursorHit>().0 else {
+        return;
+    };
+    if let Some(kind) = bevy_world.resource::<ServiceTool>().selected {
+        services::place_service(bevy_world, kind, hit.voxel);
+        return;
+    }
+    let mut transit = bevy_world.resource_mut::<TransitTool>();
+    if transit.selected.is_some() {
+        transit.stops.push(hit.voxel.xz());
+        return;
+    }
+    bevy_world.resource_mut::<BuildTool>().points.push(hit.voxel);
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, Clone, Copy)]
+    pub struct LineMode: u64 {
+        const NONE =    0b00000000;
+        const MAJOR =    0b00000001;
+        const MINOR =   0b00000010;
+        const BOTH =    0b00000011;
+    }
+}
+
+pub fn draw_line(start: IVec3, end: IVec3, mode: LineMode, mut fill: impl FnMut(IVec3)) {
+    let mut delta = IVec3::ZERO;
+    let mut delta_x2 = IVec3::ZERO;
+    let mut err = IVec3::ZERO;
+    let mut step = IVec3::ZERO;
+
+    delta = end - start;
+
+    for d in 0..3 {
+        if delta[d] < 0 {
+            delta[d] *= -1;
+            step[d] = -1;
+        } else {
+            step[d] = 1;
+        }
+    
+        delta_x2[d] = delta[d] * 2;
+    }
+
+    let mut pos = start;
+
+    (fill)(pos);
+
+    let u;
+    if delta.x >= delta.y && delta.x >= delta.z {
+        u = 0;
+    } else if delta.y >= delta.x && delta.y >= delta.z {
+        u = 1;
+    } else {
+        u = 2;
+    }
+
+    let v = (u + 1) % 3;
+    let w = (u + 2) % 3;
+
+    err[u] = delta_x2[v] - delta[u];
+    err[v] = delta_x2[w] - delta[u];
+
+    while pos[u] != end[u] {
+        pos[u] += step[u];
+       
+        if err[u] >= 0 {
+            if mode & LineMode::MAJOR != LineMode::empty() {
+                (fill)(pos);
+            }
+            pos[v] += step[v];
+            if mode & LineMode::MINOR != LineMode::empty() {
+                let mut back = pos;
+                back[u] -= step[u];
+                (fill)(back);
+            }
+            err[u] -= delta_x2[u];
+        }
+        if err[v] >= 0 {
+            if mode & LineMode::MAJOR != LineMode::empty() {
+                (fill)(pos);
+            }
+            pos[w] += step[w];
+            if mode & LineMode::MINOR != LineMode::empty() {
+                let mut back = pos;
+                back[u] -= step[u];
+                (fill)(back);
+            }
+            err[v] -= delta_x2[u];
+        }
+        err[u] += delta_x2[v];
+        err[v] += delta_x2[w];
+        (fill)(pos);
+    }
+}
+
+/// The clicked voxels as curve control points.
+fn control_points(points: &[IVec3]) -> Vec<Vec3> {
+    points.iter().map(|point| point.as_vec3()).collect()
+}
+
+fn build_road(bevy_world: &mut bevy::prelude::World) {
+    let mut tool = bevy_world.resource_mut::<BuildTool>();
+    let ready = match tool.shape.clicks() {
+        Some(clicks) => tool.points.len() >= clicks,
+        None => tool.finish && tool.points.len() >= 2,
+    };
+    tool.finish = false;
+    if !ready {
+        return;
+    }
+    let points = std::mem::take(&mut tool.points);
+    let track = tool.track;
+    let shape = tool.shape;
+    // Grading decides the heights, so the path is voxelized in plan.
+    let flat = points
+        .iter()
+        .map(|point| Vec3::new(point.x as f32, 0.0, point.z as f32))
+        .collect::<Vec<_>>();
+    let (Some(curve), Some(plan)) = (shape.build(&control_points(&points)), shape.build(&flat))
+    else {
+        let count = points.len();
+        warn!("{track:?} rejected: {count} points do not fit a {shape:?}");
+        return;
+    };
+
+    let report = validation::check_curve(&*curve, track.limits());
+    if let Some(error) = report.error {
+        warn!("{track:?} rejected: {error}");
+        return;
+    }
+
+    let (path, distances) = curve::voxelize(&*plan)
+        .into_iter()
+        .map(|(voxel, distance)| (voxel.xz(), distance))
+        .unzip();
+
+    match track {
+        Track::Road => lay_road(bevy_world, path, distances),
+        Track::Rail => lay_rail(bevy_world, path, distances),
+    }
+}
+
+/// Shows the clicked control points and the curve that clicking under the
+/// cursor would place next, with stretches outside the limits of the
+/// selected network in red.
+fn build_tool_gizmo(
+    mut gizmos: Gizmos,
+    cursor: Res<CursorHit>,
+    mut tool: ResMut<BuildTool>,
+    block_tool: Res<BlockTool>,
+) {
+    if block_tool.enabled {
+        return;
+    }
+    let surface = Vec3::new(0.5, 1.05, 0.5);
+    for point in &tool.points {
+        gizmos.sphere(point.as_vec3() + surface, Quat::IDENTITY, 0.4, Color::WHITE);
+    }
+    let Some(hit) = cursor.0 else {
+        return;
+    };
+    let mut points = tool.points.clone();
+    points.push(hit.voxel);
+    let complete = match tool.shape.clicks() {
+        Some(clicks) => points.len() == clicks,
+        None => points.len() >= 2,
+    };
+    if !complete {
+        return;
+    }
+    let (shape, track) = (tool.shape, tool.track);
+    let stale = tool.preview.as_ref().map_or(true, |preview| {
+        preview.points != points || preview.shape != shape || preview.track != track
+    });
+    if stale {
+        let Some(curve) = shape.build(&control_points(&points)) else {
+            return;
+        };
+        let report = validation::check_curve(&*curve, track.limits());
+        tool.preview = Some(Preview {
+            points,
+            shape,
+            track,
+            report,
+        });
+    }
+    let Some(preview) = &tool.preview else {
+        return;
+    };
+    for segment in &preview.report.segments {
+        let color = if segment.valid {
+            Color::GREEN
+        } else {
+            Color::RED
+        };
+        gizmos.line(segment.start + surface, segment.end + surface, color);
+    }
+}
+
+/// Grades `path` to the limits of network `K` and adds the earthworks for a
+/// cross-section reaching `half_width` either side to `edit`.
+fn grade<K: NetworkKind>(
+    bevy_world: &bevy::prelude::World,
+    path: &[IVec2],
+    distances: &[f32],
+    half_width: i32,
+    edit: &mut WorldEdit,
+) -> Option<RoadPlan> {
+    let plan = match grading::plan_road(bevy_world, path, distances, K::MAX_GRADE) {
+        Ok(plan) => plan,
+        Err(error) => {
+            warn!("{} rejected: {error}", K::NAME);
+            return None;
+        }
+    };
+    plan.earthworks(half_width, |position, block| {
+        edit.set(position, block);
+    });
+    Some(plan)
+}
+
+fn lay_road(bevy_world: &mut bevy::prelude::World, path: Vec<IVec2>, distances: Vec<f32>) {
+    let tool = bevy_world.resource::<BuildTool>();
+    let (profile, roundabouts) = (tool.profile, tool.roundabouts);
+    let half_width = profile.half_width();
+    let mut edit = WorldEdit::new();
+    let Some(plan) = grade::<Road>(bevy_world, &path, &distances, half_width, &mut edit) else {
+        return;
+    };
+
+    let decks = plan
+        .samples
+        .iter()
+        .map(|sample| sample.deck)
+        .collect::<Vec<_>>();
+    let crossings = junction::crossings(bevy_world.resource::<RoadNetwork>(), &decks);
+    let mut blocks = vec![];
+    profile.place(&plan.samples, |position, block| {
+        blocks.push((position, block))
+    });
+    pave(bevy_world, &mut edit, blocks);
+    bevy_world.resource_mut::<RoadNetwork>().add_segment(path);
+
+    for center in &crossings {
+        let junction = bevy_world.resource_scope(|bevy_world, mut junctions: Mut<Junctions>| {
+            let network = bevy_world.resource::<RoadNetwork>();
+            junctions.connect(network, *center, roundabouts).clone()
+        });
+        let mut blocks = vec![];
+        junction.geometry(&profile, |position, block| blocks.push((position, block)));
+        pave(bevy_world, &mut edit, blocks);
+    }
+    build::<Road>(bevy_world, edit);
+    info!(
+        "road built: {} columns, {} pillars, {} junctions, cost {:.0}",
+        plan.samples.len(),
+        plan.pillars.len(),
+        crossings.len(),
+        plan.cost
+    );
+}
+
+fn lay_rail(bevy_world: &mut bevy::prelude::World, path: Vec<IVec2>, distances: Vec<f32>) {
+    let profile = RailProfile::default();
+    let half_width = profile.half_width();
+    let mut edit = WorldEdit::new();
+    let Some(plan) = grade::<Rail>(bevy_world, &path, &distances, half_width, &mut edit) else {
+        return;
+    };
+    let mut blocks = vec![];
+    profile.place(&plan.samples, |position, block| {
+        blocks.push((position, block))
+    });
+    pave(bevy_world, &mut edit, blocks);
+    bevy_world.resource_mut::<RailNetwork>().add_segment(path);
+    build::<Rail>(bevy_world, edit);
+    info!(
+        "railway built: {} columns, {} pillars, cost {:.0}",
+        plan.samples.len(),
+        plan.pillars.len(),
+        plan.cost
+    );
+}
+
+/// Adds the blocks of a road or railway to `edit`, and to the network that
+/// runs on them.
+fn pave(bevy_world: &mut bevy::prelude::World, edit: &mut WorldEdit, blocks: Vec<(IVec3, Block)>) {
+    for (position, block) in blocks {
+        edit.set(position, block);
+        if block.is_track() {
+            bevy_world.resource_mut::<RailNetwork>().insert(position);
+        } else if block.is_road_surface()
+            && bevy_world.resource_mut::<RoadNetwork>().insert(position)
+        {
+            register_road_sources(bevy_world, position.xz());
+        }
+    }
+}
+
+/// Writes everything a road or railway of network `K` places in one batch,
+/// earthworks first so that the paving on top of them wins.
+fn build<K: NetworkKind>(bevy_world: &mut bevy::prelude::World, edit: WorldEdit) {
+    let deferred = edit.apply(bevy_world);
+    if deferred > 0 {
+        warn!(
+            "{} reaches {deferred} unloaded chunks and is finished as they generate",
+            K::NAME
+        );
+    }
+}
+
+fn register_road_sources(bevy_world: &mut bevy::prelude::World, column: IVec2) {
+    bevy_world.resource_scope(|bevy_world, mut noise: Mut<ScalarField2D<NoiseLevel>>| {
+        bevy_world.resource_scope(|bevy_world, mut air: Mut<ScalarField2D<AirPollution>>| {
+            let mut land_value = bevy_world.resource_mut::<ScalarField2D<LandValue>>();
+            fields::register_road_sources(&mut noise, &mut air, &mut land_value, column);
+        });
+    });
+}
+
+/// Network the build tool lays along the placed curve.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Track {
+    #[default]
+    Road,
+    Rail,
+}
+
+impl Track {
+    pub fn limits(self) -> Limits {
+        match self {
+            Track::Road => Limits::of::<Road>(),
+            Track::Rail => Limits::of::<Rail>(),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct BuildTool {
+    points: Vec<IVec3>,
+    shape: CurveShape,
+    /// Set when a spline should be built from the points clicked so far.
+    finish: bool,
+    track: Track,
+    profile: RoadProfile,
+    roundabouts: bool,
+    /// Curve last checked for the gizmo, kept until the points under
+    /// consideration or the tool settings change.
+    preview: Option<Preview>,
+}
+
+/// Control points, shape and network a [`CurveReport`] was computed for.
+struct Preview {
+    points: Vec<IVec3>,
+    shape: CurveShape,
+    track: Track,
+    report: CurveReport,
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+
+use crate::column::Heightmap;
+use crate::structure::calc_ao;
+use crate::structure::calc_cull;
+use crate::terrain::Terrain;
+use crate::world::Chunk;
+use crate::world::Dirty;
+use crate::world::VoxelWorld;
+use crate::Block;
+use crate::Structure;
+use crate::CHUNK_AXIS;
+
+const MAGIC: &[u8; 4] = b"XTWD";
+const VERSION: u32 = 2;
+
+/// Writes every loaded chunk as run-length encoded block ids, followed by the
+/// edits still waiting for chunks that have not been generated.
+///
+/// Layout (little endian): magic, version, seed, chunk axis, chunk count, then
+/// per chunk its position, run count and `(block: u8, length: u32)` runs in
+/// linear index order. After the chunks come the deferred chunk count and per
+/// chunk its position, edit count and `(index: u32, block: u8)` edits in the
+/// order they were made. Chunks are sorted by position so that identical
+/// worlds produce identical files.
+pub fn save_world(bevy_world: &mut bevy::prelude::World, path: &Path) -> io::Result<()> {
+    let mut system_state =
+        SystemState::<(Res<Terrain>, Res<VoxelWorld>, Query<(&Chunk, &Structure)>)>::new(
+            bevy_world,
+        );
+    let (terrain, world, query) = system_state.get(bevy_world);
+
+    let mut chunks = query.iter().collect::<Vec<_>>();
+    chunks.sort_by_key(|(Chunk(position), _)| position.to_array());
+
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    out.write_all(&terrain.seed.to_le_bytes())?;
+    out.write_all(&(CHUNK_AXIS as u32).to_le_bytes())?;
+    out.write_all(&(chunks.len() as u32).to_le_bytes())?;
+
+    for (Chunk(position), structure) in chunks {
+        let range = (0..structure.count()).map(|i| structure.delinearize(i));
+        let mut runs: Vec<(Block, u32)> = vec![];
+        for block in structure.get_block(range) {
+            match runs.last_mut() {
+                Some((last, length)) if *last == block => *length += 1,
+                _ => runs.push((block, 1)),
+            }
+        }
+
+        for axis in position.to_array() {
+            out.write_all(&axis.to_le_bytes())?;
+        }
+        out.write_all(&(runs.len() as u32).to_le_bytes())?;
+        for (block, length) in runs {
+            out.write_all(&[block as u8])?;
+            out.write_all(&length.to_le_bytes())?;
+        }
+    }
+
+    let mut deferred = world.deferred.iter().collect::<Vec<_>>();
+    deferred.sort_by_key(|(position, _)| position.to_array());
+    out.write_all(&(deferred.len() as u32).to_le_bytes())?;
+    for (position, edits) in deferred {
+        for axis in position.to_array() {
+            out.write_all(&axis.to_le_bytes())?;
+        }
+        out.write_all(&(edits.len() as u32).to_le_bytes())?;
+        for &(local, block) in edits {
+            let index = (local.z * CHUNK_AXIS as u32 + local.y) * CHUNK_AXIS as u32 + local.x;
+            out.write_all(&index.to_le_bytes())?;
+            out.write_all(&[block as u8])?;
+        }
+    }
+    out.flush()
+}
+
+/// Replaces the loaded chunks with those in the save at `path` and queues its
+/// deferred edits again. Version 1 saves, which have no deferred edits, are
+/// read as well.
+pub fn load_world(bevy_world: &mut bevy::prelude::World, path: &Path) -> io::Result<()> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a world save".to_string()));
+    }
+    let version = read_u32(&mut input)?;
+    if !(1..=VERSION).contains(&version) {
+        return Err(invalid(format!("unsupported save version {version}")));
+    }
+    let seed = read_u32(&mut input)?;
+    let axis = read_u32(&mut input)?;
+    if axis != CHUNK_AXIS as u32 {
+        return Err(invalid(format!("unsupported chunk axis {axis}")));
+    }
+
+    let size = UVec3::splat(axis);
+    let mut chunks = vec![];
+    for _ in 0..read_u32(&mut input)? {
+        let position = read_position(&mut input)?;
+        let mut runs = vec![];
+        for _ in 0..read_u32(&mut input)? {
+            let block = read_block(&mut input)?;
+            runs.push((block, read_u32(&mut input)?));
+        }
+        chunks.push((position, decode_runs(size, &runs)?));
+    }
+
+    let mut deferred = HashMap::new();
+    if version >= 2 {
+        let layout = Structure::new(size);
+        for _ in 0..read_u32(&mut input)? {
+            let position = read_position(&mut input)?;
+            let mut edits = vec![];
+            for _ in 0..read_u32(&mut input)? {
+                let index = read_u32(&mut input)? as usize;
+                if index >= layout.count() {
+                    return Err(invalid(format!("edit index {index} is outside its chunk")));
+                }
+                edits.push((layout.delinearize(index), read_block(&mut input)?));
+            }
+            deferred.insert(position, edits);
+        }
+    }
+
+    let view = bevy_world.resource::<VoxelWorld>().view;
+    let previous = std::mem::replace(
+        &mut *bevy_world.resource_mut::<VoxelWorld>(),
+        VoxelWorld::new(view),
+    );
+    for entity in previous.mapping.into_values() {
+        bevy_world.despawn(entity);
+    }
+    bevy_world.insert_resource(Terrain { seed });
+
+    for (position, chunk) in chunks {
+        let heightmap = Heightmap::new(&chunk);
+        let entity = bevy_world
+            .spawn((chunk, heightmap, Chunk(position), Dirty))
+            .id();
+        let mut world = bevy_world.resource_mut::<VoxelWorld>();
+        world.mapping.insert(position, entity);
+        world.loaded.insert(position);
+    }
+    bevy_world.resource_mut::<VoxelWorld>().deferred = deferred;
+    Ok(())
+}
+
+fn decode_runs(size: UVec3, runs: &[(Block, u32)]) -> io::Result<Structure> {
+    let mut chunk = Structure::new(size);
+    let total: usize = runs.iter().map(|&(_, length)| length as usize).sum();
+    if chunk.count() != total {
+        return Err(invalid(format!("chunk runs cover {total} blocks")));
+    }
+    if let [(block, _)] = runs {
+        return Ok(Structure::uniform(size, *block));
+    }
+    let blocks = runs
+        .iter()
+        .flat_map(|&(block, length)| std::iter::repeat(block).take(length as usize))
+        .enumerate()
+        .map(|(index, block)| (chunk.delinearize(index), block))
+        .collect::<Vec<_>>();
+    chunk.set_block(blocks);
+    if chunk.uniform_block().is_none() {
+        let index = 0..chunk.count() as u64;
+        calc_ao(&mut chunk, index.clone());
+        calc_cull(&mut chunk, index);
+    }
+    Ok(chunk)
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_position(input: &mut impl Read) -> io::Result<IVec3> {
+    let mut axes = [0; 3];
+    for axis in &mut axes {
+        *axis = read_u32(input)? as i32;
+    }
+    Ok(IVec3::from_array(axes))
+}
+
+fn read_block(input: &mut impl Read) -> io::Result<Block> {
+    let mut id = [0; 1];
+    input.read_exact(&mut id)?;
+    Block::ALL
+        .get(id[0] as usize)
+        .copied()
+        .ok_or_else(|| invalid(format!("unknown block id {}", id[0])))
+}
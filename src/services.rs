@@ -0,0 +1,266 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use bevy::prelude::*;
+use bevy::utils::hashbrown::HashMap;
+use bevy::utils::hashbrown::HashSet;
+
+use crate::fields::Happiness;
+use crate::fields::LandValue;
+use crate::fields::ScalarField2D;
+use crate::get_ground_level;
+use crate::network::RoadNetwork;
+use crate::network::STRAIGHT_COST;
+use crate::Block;
+use crate::WorldEdit;
+
+const ROAD_ACCESS: i32 = 6;
+const ROAD_REACH: i32 = 4;
+const FOOTPRINT: i32 = 2;
+const HEIGHT: i32 = 4;
+/// Rate at which full coverage of a column adds to its land value and
+/// happiness.
+const FIELD_RATE: f32 = 0.1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ServiceKind {
+    Police,
+    Fire,
+    Health,
+    School,
+}
+
+impl ServiceKind {
+    pub const ALL: [ServiceKind; 4] = [
+        ServiceKind::Police,
+        ServiceKind::Fire,
+        ServiceKind::Health,
+        ServiceKind::School,
+    ];
+
+    pub fn radius(self) -> u32 {
+        match self {
+            ServiceKind::Police => 160,
+            ServiceKind::Fire => 128,
+            ServiceKind::Health => 192,
+            ServiceKind::School => 96,
+        }
+    }
+
+    pub fn block(self) -> Block {
+        match self {
+            ServiceKind::Police => Block::Police,
+            ServiceKind::Fire => Block::Fire,
+            ServiceKind::Health => Block::Health,
+            ServiceKind::School => Block::School,
+        }
+    }
+
+    fn happiness_weight(self) -> f32 {
+        match self {
+            ServiceKind::Police => 0.2,
+            ServiceKind::Fire => 0.2,
+            ServiceKind::Health => 0.35,
+            ServiceKind::School => 0.25,
+        }
+    }
+
+    fn land_value_weight(self) -> f32 {
+        match self {
+            ServiceKind::Police => 0.15,
+            ServiceKind::Fire => 0.1,
+            ServiceKind::Health => 0.2,
+            ServiceKind::School => 0.3,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct ServiceBuilding {
+    pub kind: ServiceKind,
+    pub position: IVec3,
+}
+
+#[derive(Resource, Default)]
+pub struct ServiceCoverage {
+    maps: HashMap<ServiceKind, HashMap<IVec2, f32>>,
+    /// Land value and happiness sources the current coverage added to the
+    /// fields, taken back out when it is recomputed.
+    sources: HashMap<IVec2, (f32, f32)>,
+    dirty: bool,
+}
+
+impl ServiceCoverage {
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn level(&self, kind: ServiceKind, column: IVec2) -> f32 {
+        self.maps
+            .get(&kind)
+            .and_then(|map| map.get(&column))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    pub fn happiness(&self, column: IVec2) -> f32 {
+        ServiceKind::ALL
+            .iter()
+            .map(|&kind| self.level(kind, column) * kind.happiness_weight())
+            .sum()
+    }
+
+    pub fn land_value(&self, column: IVec2) -> f32 {
+        ServiceKind::ALL
+            .iter()
+            .map(|&kind| self.level(kind, column) * kind.land_value_weight())
+            .sum()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ServiceTool {
+    pub selected: Option<ServiceKind>,
+}
+
+fn road_distances(network: &RoadNetwork, seeds: &[IVec2], radius: u32) -> HashMap<IVec2, u32> {
+    let limit = radius * STRAIGHT_COST;
+    let mut distances = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    for &seed in seeds {
+        distances.insert(seed, 0);
+        queue.push(Reverse((0, seed.x, seed.y)));
+    }
+    while let Some(Reverse((distance, x, z))) = queue.pop() {
+        let column = IVec2::new(x, z);
+        if distances.get(&column).is_some_and(|&best| best < distance) {
+            continue;
+        }
+        for (neighbor, cost) in network.neighbors(column) {
+            let next = distance + cost;
+            if next > limit {
+                continue;
+            }
+            if distances.get(&neighbor).map_or(true, |&best| next < best) {
+                distances.insert(neighbor, next);
+                queue.push(Reverse((next, neighbor.x, neighbor.y)));
+            }
+        }
+    }
+    distances
+}
+
+fn coverage_map(network: &RoadNetwork, building: &ServiceBuilding) -> HashMap<IVec2, f32> {
+    let origin = building.position.xz();
+    let seeds = (-ROAD_ACCESS..=ROAD_ACCESS)
+        .flat_map(|x| (-ROAD_ACCESS..=ROAD_ACCESS).map(move |z| origin + IVec2::new(x, z)))
+        .filter(|column| network.contains(*column))
+        .collect::<Vec<_>>();
+
+    let radius = building.kind.radius();
+    let mut coverage = HashMap::new();
+    for (road, distance) in road_distances(network, &seeds, radius) {
+        let level = 1.0 - distance as f32 / (radius * STRAIGHT_COST) as f32;
+        for x in -ROAD_REACH..=ROAD_REACH {
+            for z in -ROAD_REACH..=ROAD_REACH {
+                let column = road + IVec2::new(x, z);
+                let entry = coverage.entry(column).or_insert(0.0f32);
+                *entry = entry.max(level);
+            }
+        }
+    }
+    for x in -FOOTPRINT..=FOOTPRINT {
+        for z in -FOOTPRINT..=FOOTPRINT {
+            coverage.insert(origin + IVec2::new(x, z), 1.0);
+        }
+    }
+    coverage
+}
+
+pub fn update_coverage(
+    mut coverage: ResMut<ServiceCoverage>,
+    mut land_value: ResMut<ScalarField2D<LandValue>>,
+    mut happiness: ResMut<ScalarField2D<Happiness>>,
+    network: Res<RoadNetwork>,
+    buildings: Query<&ServiceBuilding>,
+) {
+    if !coverage.dirty && !network.is_changed() {
+        return;
+    }
+    coverage.dirty = false;
+
+    let mut maps = HashMap::<ServiceKind, HashMap<IVec2, f32>>::new();
+    for building in buildings.iter() {
+        let map = maps.entry(building.kind).or_default();
+        for (column, level) in coverage_map(&network, building) {
+            let entry = map.entry(column).or_insert(0.0);
+            *entry = entry.max(level);
+        }
+    }
+    coverage.maps = maps;
+
+    for (&column, &(value, happy)) in &coverage.sources {
+        land_value.add_sink(column, value);
+        happiness.add_sink(column, happy);
+    }
+    let columns = coverage
+        .maps
+        .values()
+        .flat_map(|map| map.keys().copied())
+        .collect::<HashSet<_>>();
+    let sources = columns
+        .into_iter()
+        .map(|column| {
+            let value = coverage.land_value(column) * FIELD_RATE;
+            let happy = coverage.happiness(column) * FIELD_RATE;
+            land_value.add_source(column, value);
+            happiness.add_source(column, happy);
+            (column, (value, happy))
+        })
+        .collect();
+    coverage.sources = sources;
+}
+
+pub fn service_tool_input(keys: Res<Input<KeyCode>>, mut tool: ResMut<ServiceTool>) {
+    let selection = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4]
+        .into_iter()
+        .zip(ServiceKind::ALL)
+        .find(|(key, _)| keys.just_pressed(*key));
+    if let Some((_, kind)) = selection {
+        tool.selected = Some(kind);
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        tool.selected = None;
+    }
+}
+
+pub fn place_service(bevy_world: &mut bevy::prelude::World, kind: ServiceKind, position: IVec3) {
+    let ground = match get_ground_level(bevy_world, position) {
+        Ok(ground) => ground,
+        Err(error) => {
+            warn!("{kind:?} not placed: {error}");
+            return;
+        }
+    };
+    let mut edit = WorldEdit::new();
+    for x in -FOOTPRINT..=FOOTPRINT {
+        for z in -FOOTPRINT..=FOOTPRINT {
+            for y in 0..HEIGHT {
+                let voxel = IVec3::new(position.x + x, ground + y, position.z + z);
+                edit.set(voxel, kind.block());
+            }
+        }
+    }
+    edit.apply(bevy_world);
+    bevy_world.spawn(ServiceBuilding {
+        kind,
+        position: IVec3::new(position.x, ground, position.z),
+    });
+    bevy_world.resource_mut::<ServiceCoverage>().mark_dirty();
+}
+
+pub fn coverage_tint(level: f32) -> Vec4 {
+    let low = Vec4::new(0.9, 0.15, 0.1, 1.0);
+    let high = Vec4::new(0.1, 0.9, 0.2, 1.0);
+    low.lerp(high, level.clamp(0.0, 1.0))
+}
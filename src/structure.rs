@@ -0,0 +1,426 @@
+use std::iter;
+use std::mem;
+
+use bevy::prelude::*;
+use bevy::utils::hashbrown::HashMap;
+use bitflags::bitflags;
+
+use crate::palette::PalettedChannel;
+use crate::Block;
+
+const CULL_MASK: u64 = 0x3f;
+const AO_SHIFT: u32 = 8;
+
+#[derive(Component)]
+pub struct Structure {
+    size: UVec3,
+    blocks: PalettedChannel,
+    surface: HashMap<u32, u64>,
+}
+
+impl Structure {
+    pub fn new(size: UVec3) -> Self {
+        Self::uniform(size, Block::Void)
+    }
+
+    pub fn uniform(size: UVec3, block: Block) -> Self {
+        let UVec3 {
+            x: sx,
+            y: sy,
+            z: sz,
+        } = size;
+        Structure {
+            size,
+            blocks: PalettedChannel::new((sx * sy * sz) as usize, block as u64),
+            surface: HashMap::new(),
+        }
+    }
+
+    pub fn uniform_block(&self) -> Option<Block> {
+        self.blocks
+            .uniform()
+            .map(|id| unsafe { mem::transmute::<u64, Block>(id) })
+    }
+
+    fn block_at(&self, index: usize) -> Block {
+        unsafe { mem::transmute(self.blocks.get(index)) }
+    }
+
+    pub fn get_block<'a>(
+        &'a self,
+        position: impl IntoIterator<Item = UVec3> + 'a,
+    ) -> impl Iterator<Item = Block> + 'a {
+        position
+            .into_iter()
+            .map(|pos| self.block_at(self.linearize(pos)))
+    }
+
+    pub fn set_block(&mut self, data: impl IntoIterator<Item = (UVec3, Block)>) {
+        let data = data
+            .into_iter()
+            .map(|(pos, block)| (self.linearize(pos), block as u64))
+            .collect::<Vec<_>>();
+        for &(index, id) in &data {
+            if id == Block::Air as u64 {
+                self.surface.remove(&(index as u32));
+            }
+        }
+        self.blocks.set_many(data);
+        self.blocks.compact();
+    }
+
+    fn update_surface(&mut self, index: usize, f: impl FnOnce(u64) -> u64) {
+        if matches!(self.block_at(index), Block::Air) {
+            return;
+        }
+        let key = index as u32;
+        let data = f(self.surface.get(&key).copied().unwrap_or(0));
+        if data == 0 {
+            self.surface.remove(&key);
+        } else {
+            self.surface.insert(key, data);
+        }
+    }
+
+    pub fn get_cull<'a>(
+        &'a self,
+        position: impl IntoIterator<Item = UVec3> + 'a,
+    ) -> impl Iterator<Item = Direction> + 'a {
+        position.into_iter().map(|pos| {
+            let data = self
+                .surface
+                .get(&(self.linearize(pos) as u32))
+                .copied()
+                .unwrap_or(0);
+            Direction::from_bits(data & CULL_MASK).unwrap()
+        })
+    }
+
+    pub fn set_cull(&mut self, data: impl IntoIterator<Item = (UVec3, Direction)>) {
+        for (pos, dir) in data {
+            let index = self.linearize(pos);
+            self.update_surface(index, |data| (data & !CULL_MASK) | dir.bits());
+        }
+    }
+
+    /// True if any voxel shows a face, as of the last cull update.
+    pub fn has_visible_faces(&self) -> bool {
+        self.surface.values().any(|data| data & CULL_MASK != 0)
+    }
+
+    pub fn get_ao<'a>(
+        &'a self,
+        position: impl IntoIterator<Item = UVec3> + 'a,
+    ) -> impl Iterator<Item = [Vec4; 6]> + 'a {
+        position.into_iter().map(|pos| {
+            let data = self
+                .surface
+                .get(&(self.linearize(pos) as u32))
+                .copied()
+                .unwrap_or(0)
+                >> AO_SHIFT;
+            let mut ao = [Vec4::splat(0.0); 6];
+            for x in 0..6 {
+                for y in 0..4 {
+                    ao[x][y] = ((data >> ((x * 8) + y * 2)) & 3) as f32 / 3.0;
+                }
+            }
+            ao
+        })
+    }
+
+    pub fn set_ao(&mut self, data: impl IntoIterator<Item = (UVec3, [Vec4; 6])>) {
+        for (pos, ao) in data {
+            let mut packed = 0u64;
+            for x in 0..6 {
+                for y in 0..4 {
+                    packed |= ((ao[x][y] * 3.0).round() as u64) << ((x * 8) + y * 2);
+                }
+            }
+            let index = self.linearize(pos);
+            self.update_surface(index, |data| (data & CULL_MASK) | (packed << AO_SHIFT));
+        }
+    }
+
+    pub fn size(&self) -> UVec3 {
+        self.size
+    }
+
+    pub fn linearize(&self, position: UVec3) -> usize {
+        let UVec3 { y: sy, x: sx, .. } = self.size();
+        let UVec3 { x, y, z } = position;
+        ((z * sy + y) * sx + x) as usize
+    }
+
+    pub fn delinearize(&self, index: usize) -> UVec3 {
+        let UVec3 {
+            x: sx,
+            y: sy,
+            z: sz,
+        } = self.size();
+        let mut idx = index as u32;
+        let z = idx / (sx * sy);
+        idx -= (z * sx * sy);
+        let y = idx / sx;
+        let x = idx % sx;
+        UVec3 { x, y, z }
+    }
+
+    pub fn count(&self) -> usize {
+        let UVec3 { x, y, z } = self.size();
+        (x * y * z) as usize
+    }
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+    pub struct Direction: u64 {
+        const LEFT =    0b00000001;
+        const RIGHT =   0b00000010;
+        const DOWN =    0b00000100;
+        const UP =      0b00001000;
+        const BACK =    0b00010000;
+        const FORWARD =  0b00100000;
+        const ALL =  0b00111111;
+    }
+}
+
+impl Direction {
+    pub fn from_normal(normal: IVec3) -> Self {
+        match normal.to_array() {
+            [-1, 0, 0] => Self::LEFT,
+            [1, 0, 0] => Self::RIGHT,
+            [0, -1, 0] => Self::DOWN,
+            [0, 1, 0] => Self::UP,
+            [0, 0, -1] => Self::BACK,
+            [0, 0, 1] => Self::FORWARD,
+            _ => Self::empty(),
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        if self == Self::LEFT {
+            Self::RIGHT
+        } else if self == Self::RIGHT {
+            Self::LEFT
+        } else if self == Self::DOWN {
+            Self::UP
+        } else if self == Self::UP {
+            Self::DOWN
+        } else if self == Self::BACK {
+            Self::FORWARD
+        } else if self == Self::FORWARD {
+            Self::BACK
+        } else {
+            panic!("cannot have opposite of multiple directions");
+        }
+    }
+}
+
+/// Read-only view of a chunk and the chunks around it, addressed in the
+/// center chunk's local coordinates. Positions that fall into a missing
+/// neighbor read as `None`.
+pub struct Neighborhood<'a> {
+    chunks: [Option<&'a Structure>; 27],
+}
+
+impl<'a> Neighborhood<'a> {
+    pub fn new(structure: &'a Structure) -> Self {
+        Self::gather(|offset| (offset == IVec3::ZERO).then_some(structure))
+    }
+
+    pub fn gather(mut f: impl FnMut(IVec3) -> Option<&'a Structure>) -> Self {
+        let mut chunks = [None; 27];
+        all_neighbors(IVec3::ZERO, |offset| chunks[Self::slot(offset)] = (f)(offset));
+        Neighborhood { chunks }
+    }
+
+    fn slot(offset: IVec3) -> usize {
+        let offset = offset + 1;
+        ((offset.z * 3 + offset.y) * 3 + offset.x) as usize
+    }
+
+    pub fn center(&self) -> &'a Structure {
+        self.chunks[Self::slot(IVec3::ZERO)].unwrap()
+    }
+
+    pub fn get_block(&self, position: IVec3) -> Option<Block> {
+        let size = self.center().size().as_ivec3();
+        let offset = position.div_euclid(size);
+        if offset.abs().max_element() > 1 {
+            return None;
+        }
+        let structure = self.chunks[Self::slot(offset)]?;
+        let local = position.rem_euclid(size).as_uvec3();
+        Some(structure.block_at(structure.linearize(local)))
+    }
+}
+
+pub(crate) fn border_indices(size: UVec3) -> impl Iterator<Item = u64> {
+    let UVec3 {
+        x: sx,
+        y: sy,
+        z: sz,
+    } = size;
+    (0..sz).flat_map(move |z| {
+        (0..sy).flat_map(move |y| {
+            let face = z == 0 || z == sz - 1 || y == 0 || y == sy - 1;
+            let step = if face || sx <= 2 { 1 } else { sx - 1 };
+            (0..sx)
+                .step_by(step as usize)
+                .map(move |x| ((z * sy + y) * sx + x) as u64)
+        })
+    })
+}
+
+/// Indices of `positions` and of every voxel around them that lies inside a
+/// structure of `size`, each listed once.
+pub(crate) fn surrounding_indices(
+    size: UVec3,
+    positions: impl IntoIterator<Item = UVec3>,
+) -> Vec<u64> {
+    let mut index = positions
+        .into_iter()
+        .flat_map(|position| {
+            let mut around = vec![];
+            all_neighbors(position.as_ivec3(), |neighbor| {
+                if neighbor.cmpge(IVec3::ZERO).all() && neighbor.cmplt(size.as_ivec3()).all() {
+                    let UVec3 { x, y, z } = neighbor.as_uvec3();
+                    around.push(((z * size.y + y) * size.x + x) as u64);
+                }
+            });
+            around
+        })
+        .collect::<Vec<_>>();
+    index.sort_unstable();
+    index.dedup();
+    index
+}
+
+/// Recomputes faces and ambient occlusion after `Structure::set_block`
+/// changed the voxels at `positions`, which also changes what the voxels
+/// around them can show.
+pub fn refresh_surface(structure: &mut Structure, positions: impl IntoIterator<Item = UVec3>) {
+    let index = surrounding_indices(structure.size(), positions);
+    calc_cull(structure, index.iter().copied());
+    calc_ao(structure, index.into_iter());
+}
+
+pub fn calc_cull(structure: &mut Structure, index: impl Iterator<Item = u64>) {
+    let cull = cull_faces(&Neighborhood::new(structure), index);
+    structure.set_cull(cull);
+}
+
+pub(crate) fn cull_faces(
+    neighborhood: &Neighborhood,
+    index: impl Iterator<Item = u64>,
+) -> Vec<(UVec3, Direction)> {
+    let structure = neighborhood.center();
+    index
+        .map(|index| {
+            let position = structure.delinearize(index as usize);
+            let mut direction = structure.get_cull(iter::once(position)).next().unwrap();
+            let mut dir_iter = (0..6)
+                .map(|x| 1 << x)
+                .map(Direction::from_bits)
+                .map(Option::unwrap);
+            for d in 0..3 {
+                for n in (-1..=1).step_by(2) {
+                    let current_direction = dir_iter.next().unwrap();
+                    let mut normal = IVec3::default();
+                    normal[d] = n;
+                    match neighborhood.get_block(position.as_ivec3() + normal) {
+                        Some(Block::Air) => direction |= current_direction,
+                        Some(_) => direction &= !current_direction,
+                        None => {}
+                    }
+                }
+            }
+            (position, direction)
+        })
+        .collect()
+}
+
+pub(crate) fn all_neighbors(position: IVec3, mut f: impl FnMut(IVec3)) {
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                (f)(position + IVec3 { x, y, z });
+            }
+        }
+    }
+}
+
+pub fn calc_ao(structure: &mut Structure, index: impl Iterator<Item = u64>) {
+    let ao = ao_faces(&Neighborhood::new(structure), index);
+    structure.set_ao(ao);
+}
+
+pub(crate) fn ao_faces(
+    neighborhood: &Neighborhood,
+    index: impl Iterator<Item = u64>,
+) -> Vec<(UVec3, [Vec4; 6])> {
+    let structure = neighborhood.center();
+    index
+        .map(|index| {
+            let position = structure.delinearize(index as usize);
+            let mut ao = [Vec4::ZERO; 6];
+            let mut dir_iter = (0..6)
+                .map(|x| 1 << x)
+                .map(Direction::from_bits)
+                .map(Option::unwrap);
+            for d in 0..3 {
+                for n in (-1..=1).step_by(2) {
+                    let current_direction = dir_iter.next().unwrap();
+                    let mut normal = IVec3::default();
+                    normal[d] = n;
+                    let direction_index = current_direction.bits().trailing_zeros() as usize;
+                    ao[direction_index] = voxel_ao(
+                        neighborhood,
+                        position.as_ivec3() + normal,
+                        IVec3 {
+                            x: normal.z.abs(),
+                            y: normal.x.abs(),
+                            z: normal.y.abs(),
+                        },
+                        IVec3 {
+                            x: normal.y.abs(),
+                            y: normal.z.abs(),
+                            z: normal.x.abs(),
+                        },
+                    );
+                }
+            }
+            (position, ao)
+        })
+        .collect()
+}
+
+fn voxel_ao(neighborhood: &Neighborhood, pos: IVec3, d1: IVec3, d2: IVec3) -> Vec4 {
+    let voxel_present = |pos: IVec3| -> f32 {
+        match neighborhood.get_block(pos) {
+            Some(block) => !matches!(block, Block::Air) as i32 as f32,
+            None => 0.0,
+        }
+    };
+    let vertex_ao =
+        |side: Vec2, corner: f32| (side.x + side.y + f32::max(corner, side.x * side.y)) / 3.0;
+    let side = Vec4::new(
+        (voxel_present)(pos + d1),
+        (voxel_present)(pos + d2),
+        (voxel_present)(pos - d1),
+        (voxel_present)(pos - d2),
+    );
+    let corner = Vec4::new(
+        (voxel_present)(pos + d1 + d2),
+        (voxel_present)(pos - d1 + d2),
+        (voxel_present)(pos - d1 - d2),
+        (voxel_present)(pos + d1 - d2),
+    );
+    1.0 - Vec4::new(
+        (vertex_ao)(Vec2::new(side.x, side.y), corner.x),
+        (vertex_ao)(Vec2::new(side.y, side.z), corner.y),
+        (vertex_ao)(Vec2::new(side.z, side.w), corner.z),
+        (vertex_ao)(Vec2::new(side.w, side.x), corner.w),
+    )
+}
@@ -0,0 +1,253 @@
+use bevy::prelude::*;
+
+use crate::Block;
+use crate::Structure;
+use crate::VoxelWorld;
+use crate::CHUNK_AXIS;
+
+/// Selects the seed the voxel world generates its terrain from.
+pub struct TerrainPlugin {
+    pub seed: u32,
+}
+
+impl Default for TerrainPlugin {
+    fn default() -> Self {
+        TerrainPlugin { seed: DEFAULT_SEED }
+    }
+}
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Terrain { seed: self.seed });
+    }
+}
+
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Terrain {
+    pub seed: u32,
+}
+
+const DENSITY_BOUND: f64 = 2.0;
+/// Spacing of the noise lattice that chunk densities are interpolated from.
+const NOISE_SCALE: i32 = 32;
+
+pub const DEFAULT_SEED: u32 = 400;
+
+pub(crate) fn terrain_noise(seed: u32) -> noise::Fbm<noise::Perlin> {
+    noise::Fbm::<noise::Perlin>::new(seed)
+}
+
+fn lattice_density(perlin: &noise::Fbm<noise::Perlin>, position: IVec3) -> f64 {
+    use noise::NoiseFn;
+    perlin.get([
+        position.x as f64 * 0.0015,
+        position.y as f64 * 0.0015,
+        position.z as f64 * 0.0015,
+    ])
+}
+
+fn density_mod(y: i32) -> f64 {
+    (32isize - y as isize) as f64 * 0.035
+}
+
+fn chunk_fill(perlin: &noise::Fbm<noise::Perlin>, position: IVec3) -> Option<Block> {
+    let base = position * CHUNK_AXIS as i32;
+    let mut min = f64::MAX;
+    let mut max = f64::MIN;
+    for corner in 0..8 {
+        let offset = IVec3::new(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+        let density = lattice_density(perlin, base + offset * CHUNK_AXIS as i32);
+        min = min.min(density);
+        max = max.max(density);
+    }
+    if min + density_mod(base.y + CHUNK_AXIS as i32 - 1) > 0.0 {
+        Some(Block::Grass)
+    } else if max + density_mod(base.y) <= 0.0 {
+        Some(Block::Air)
+    } else {
+        None
+    }
+}
+
+fn surface_fill(perlin: &noise::Fbm<noise::Perlin>, position: IVec3) -> Option<Block> {
+    match chunk_fill(perlin, position) {
+        Some(Block::Grass) if chunk_fill(perlin, position + IVec3::Y) == Some(Block::Air) => None,
+        fill => fill,
+    }
+}
+
+fn terrain_chunk_bounds() -> (i32, i32) {
+    let span = DENSITY_BOUND / 0.035;
+    let min = ((32.0 - span) / CHUNK_AXIS as f64).floor() as i32;
+    let max = ((32.0 + span) / CHUNK_AXIS as f64).floor() as i32;
+    (min, max)
+}
+
+fn surface_range(perlin: &noise::Fbm<noise::Perlin>, column: IVec2) -> (i32, i32) {
+    let (min, max) = terrain_chunk_bounds();
+    let surface = (min..=max)
+        .filter(|&y| surface_fill(perlin, IVec3::new(column.x, y, column.y)).is_none())
+        .collect::<Vec<_>>();
+    match (surface.first(), surface.last()) {
+        (Some(&low), Some(&high)) => (low, high),
+        _ => (min, max),
+    }
+}
+
+pub(crate) fn column_range(world: &mut VoxelWorld, perlin: &noise::Fbm<noise::Perlin>, column: IVec2) -> (i32, i32) {
+    let mut range = (i32::MAX, i32::MIN);
+    for x in -1..=1 {
+        for z in -1..=1 {
+            let neighbor = column + IVec2::new(x, z);
+            let (low, high) = *world
+                .columns
+                .entry(neighbor)
+                .or_insert_with(|| surface_range(perlin, neighbor));
+            range = (range.0.min(low - 1), range.1.max(high + 1));
+        }
+    }
+    range
+}
+
+/// Height of the first air voxel above the terrain the generator produces
+/// in `column`, for planning over chunks that have not been generated yet.
+pub fn generated_height(seed: u32, column: IVec2) -> i32 {
+    let perlin = terrain_noise(seed);
+    let cell = column.div_euclid(IVec2::splat(NOISE_SCALE)) * NOISE_SCALE;
+    let t = (column - cell).as_dvec2() / NOISE_SCALE as f64;
+    let lattice = |y: i32| {
+        let corner = |x: i32, z: i32| {
+            let position = IVec3::new(cell.x + x * NOISE_SCALE, y, cell.y + z * NOISE_SCALE);
+            lattice_density(&perlin, position)
+        };
+        let near = corner(0, 0) * (1.0 - t.x) + corner(1, 0) * t.x;
+        let far = corner(0, 1) * (1.0 - t.x) + corner(1, 1) * t.x;
+        near * (1.0 - t.y) + far * t.y
+    };
+
+    let (min, max) = terrain_chunk_bounds();
+    let bottom = min * CHUNK_AXIS as i32;
+    let mut y1 = (max + 1) * CHUNK_AXIS as i32;
+    let mut above = lattice(y1);
+    while y1 > bottom {
+        let y0 = y1 - NOISE_SCALE;
+        let below = lattice(y0);
+        for y in (y0..y1).rev() {
+            let fraction = (y - y0) as f64 / NOISE_SCALE as f64;
+            let density = below + (above - below) * fraction;
+            if density + density_mod(y) > 0.0 {
+                return y + 1;
+            }
+        }
+        (y1, above) = (y0, below);
+    }
+    bottom
+}
+
+pub fn gen_chunk(seed: u32, position: IVec3) -> Structure {
+    let size = UVec3::new(CHUNK_AXIS as u32, CHUNK_AXIS as u32, CHUNK_AXIS as u32);
+    let perlin = terrain_noise(seed);
+    if let Some(block) = surface_fill(&perlin, position) {
+        return Structure::uniform(size, block);
+    }
+    let mut chunk = Structure::new(size);
+    let UVec3 {
+        x: sx,
+        y: sy,
+        z: sz,
+    } = chunk.size();
+
+    let mut noise_values = vec![];
+
+    for z in 0..=CHUNK_AXIS as i32 / NOISE_SCALE {
+        for y in 0..=CHUNK_AXIS as i32 / NOISE_SCALE {
+            for x in 0..=CHUNK_AXIS as i32 / NOISE_SCALE {
+                let nx = position.x * CHUNK_AXIS as i32 + x * NOISE_SCALE;
+                let ny = position.y * CHUNK_AXIS as i32 + y * NOISE_SCALE;
+                let nz = position.z * CHUNK_AXIS as i32 + z * NOISE_SCALE;
+                let density = lattice_density(&perlin, IVec3::new(nx, ny, nz));
+                noise_values.push(density);
+            }
+        }
+    }
+
+    fn lerp3d(
+        xm_ym_zm: f64,
+        xp_ym_zm: f64,
+        xm_yp_zm: f64,
+        xp_yp_zm: f64,
+        xm_ym_zp: f64,
+        xp_ym_zp: f64,
+        xm_yp_zp: f64,
+        xp_yp_zp: f64,
+        x: f64,
+        y: f64,
+        z: f64,
+    ) -> f64 {
+        (xm_ym_zm * (1.0 - x) * (1.0 - y) * (1.0 - z))
+            + (xp_ym_zm * x * (1.0 - y) * (1.0 - z))
+            + (xm_yp_zm * (1.0 - x) * y * (1.0 - z))
+            + (xp_yp_zm * x * y * (1.0 - z))
+            + (xm_ym_zp * (1.0 - x) * (1.0 - y) * z)
+            + (xp_ym_zp * x * (1.0 - y) * z)
+            + (xm_yp_zp * (1.0 - x) * y * z)
+            + (xp_yp_zp * x * y * z)
+    }
+
+    let mut blocks = vec![];
+    let smx = sx as usize / NOISE_SCALE as usize + 1;
+    let smy = sy as usize / NOISE_SCALE as usize + 1;
+    for z in 0..CHUNK_AXIS as u32 {
+        for x in 0..CHUNK_AXIS as u32 {
+            for y in 0..CHUNK_AXIS as u32 {
+                let ix = x as usize % NOISE_SCALE as usize;
+                let iy = y as usize % NOISE_SCALE as usize;
+                let iz = z as usize % NOISE_SCALE as usize;
+                let ny = position.y * sy as i32 + y as i32;
+
+                let mx0 = x as usize / NOISE_SCALE as usize;
+                let my0 = y as usize / NOISE_SCALE as usize;
+                let mz0 = z as usize / NOISE_SCALE as usize;
+
+                let mx1 = mx0 + 1;
+                let my1 = my0 + 1;
+                let mz1 = mz0 + 1;
+
+                let x0y0z0 = noise_values[(mz0 * smy + my0) * smx + mx0];
+                let x1y0z0 = noise_values[(mz0 * smy + my0) * smx + mx1];
+                let x0y1z0 = noise_values[(mz0 * smy + my1) * smx + mx0];
+                let x0y0z1 = noise_values[(mz1 * smy + my0) * smx + mx0];
+                let x1y1z0 = noise_values[(mz0 * smy + my1) * smx + mx1];
+                let x0y1z1 = noise_values[(mz1 * smy + my1) * smx + mx0];
+                let x1y0z1 = noise_values[(mz1 * smy + my0) * smx + mx1];
+                let x1y1z1 = noise_values[(mz1 * smy + my1) * smx + mx1];
+
+                let density = lerp3d(
+                    x0y0z0,
+                    x1y0z0,
+                    x0y1z0,
+                    x1y1z0,
+                    x0y0z1,
+                    x1y0z1,
+                    x0y1z1,
+                    x1y1z1,
+                    ix as f64 / NOISE_SCALE as f64,
+                    iy as f64 / NOISE_SCALE as f64,
+                    iz as f64 / NOISE_SCALE as f64,
+                );
+
+                let density_mod = density_mod(ny);
+                blocks.push((
+                    UVec3 { x, y, z },
+                    if density + density_mod > 0.0 {
+                        Block::Grass
+                    } else {
+                        Block::Air
+                    },
+                ));
+            }
+        }
+    }
+    chunk.set_block(blocks);
+    chunk
+}
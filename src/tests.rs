@@ -0,0 +1,846 @@
+use bevy::prelude::*;
+
+use crate::column::heightmap;
+use crate::column::solid_below;
+use crate::column::surface_height;
+use crate::column::Heightmap;
+use crate::curve::voxelize;
+use crate::curve::Arc;
+use crate::curve::ArcLength;
+use crate::curve::CatmullRom;
+use crate::curve::Curve;
+use crate::curve::CurveShape;
+use crate::curve::Line;
+use crate::fields::Happiness;
+use crate::fields::LandValue;
+use crate::fields::ScalarField2D;
+use crate::get_block;
+use crate::get_ground_level;
+use crate::grading::grade_profile;
+use crate::grading::ProfileSample;
+use crate::grading::Section;
+use crate::junction::crossings;
+use crate::junction::Junction;
+use crate::junction::Junctions;
+use crate::junction::Turn;
+use crate::junction::TurnRestrictions;
+use crate::meshing::create_structure_mesh;
+use crate::network::Rail;
+use crate::network::Road;
+use crate::network::RoadNetwork;
+use crate::profile::RoadProfile;
+use crate::save::load_world;
+use crate::save::save_world;
+use crate::services::update_coverage;
+use crate::services::ServiceBuilding;
+use crate::services::ServiceCoverage;
+use crate::services::ServiceKind;
+use crate::set_block;
+use crate::structure::all_neighbors;
+use crate::structure::calc_ao;
+use crate::structure::calc_cull;
+use crate::structure::cull_faces;
+use crate::structure::refresh_surface;
+use crate::structure::Neighborhood;
+use crate::terrain::gen_chunk;
+use crate::terrain::generated_height;
+use crate::terrain::Terrain;
+use crate::terrain::DEFAULT_SEED;
+use crate::transit::TransitError;
+use crate::transit::TransitKind;
+use crate::transit::TransitLine;
+use crate::validation::check_curve;
+use crate::validation::CurveError;
+use crate::validation::Limits;
+use crate::world::consolidate;
+use crate::world::Chunk;
+use crate::world::Dirty;
+use crate::world::WorldEdit;
+use crate::Block;
+use crate::Direction;
+use crate::RayExt;
+use crate::Structure;
+use crate::VoxelWorld;
+use crate::CHUNK_AXIS;
+
+const SIDES: [(Direction, IVec3); 6] = [
+    (Direction::LEFT, IVec3::NEG_X),
+    (Direction::RIGHT, IVec3::X),
+    (Direction::DOWN, IVec3::NEG_Y),
+    (Direction::UP, IVec3::Y),
+    (Direction::BACK, IVec3::NEG_Z),
+    (Direction::FORWARD, IVec3::Z),
+];
+
+fn fixture(size: UVec3, solid: &[UVec3]) -> Structure {
+    let mut structure = Structure::uniform(size, Block::Air);
+    structure.set_block(solid.iter().map(|&position| (position, Block::Stone)));
+    let index = 0..structure.count() as u64;
+    calc_ao(&mut structure, index.clone());
+    calc_cull(&mut structure, index);
+    structure
+}
+
+fn cull_at(structure: &Structure, position: UVec3) -> Direction {
+    structure.get_cull([position]).next().unwrap()
+}
+
+fn ao_at(structure: &Structure, position: UVec3) -> [Vec4; 6] {
+    structure.get_ao([position]).next().unwrap()
+}
+
+/// Classic per-vertex ambient occlusion: a vertex next to two solid sides is
+/// fully occluded, otherwise each solid side or corner darkens it by a third.
+fn reference_ao(solid: impl Fn(IVec3) -> bool, voxel: IVec3, normal: IVec3) -> Vec4 {
+    let face = voxel + normal;
+    let d1 = IVec3::new(normal.z.abs(), normal.x.abs(), normal.y.abs());
+    let d2 = IVec3::new(normal.y.abs(), normal.z.abs(), normal.x.abs());
+    let corners = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+    let mut ao = Vec4::ZERO;
+    for (vertex, (a, b)) in corners.into_iter().enumerate() {
+        let side1 = solid(face + d1 * a);
+        let side2 = solid(face + d2 * b);
+        let corner = solid(face + d1 * a + d2 * b);
+        ao[vertex] = if side1 && side2 {
+            0.0
+        } else {
+            (3 - side1 as i32 - side2 as i32 - corner as i32) as f32 / 3.0
+        };
+    }
+    ao
+}
+
+#[test]
+fn opposite_directions() {
+    for (direction, normal) in SIDES {
+        assert_eq!(direction.opposite(), Direction::from_normal(-normal));
+        assert_eq!(direction.opposite().opposite(), direction);
+    }
+}
+
+#[test]
+fn isolated_voxel_shows_every_face() {
+    let structure = fixture(UVec3::splat(3), &[UVec3::ONE]);
+    assert_eq!(cull_at(&structure, UVec3::ONE), Direction::all());
+}
+
+#[test]
+fn neighbor_hides_shared_face() {
+    for (direction, normal) in SIDES {
+        let neighbor = (IVec3::ONE + normal).as_uvec3();
+        let structure = fixture(UVec3::splat(3), &[UVec3::ONE, neighbor]);
+        assert_eq!(
+            cull_at(&structure, UVec3::ONE),
+            Direction::all() - direction,
+            "{direction:?}"
+        );
+        assert_eq!(
+            cull_at(&structure, neighbor),
+            Direction::all() - direction.opposite(),
+            "{direction:?}"
+        );
+    }
+}
+
+#[test]
+fn faces_on_structure_bounds_stay_hidden() {
+    let structure = fixture(UVec3::new(2, 1, 1), &[UVec3::ZERO]);
+    assert_eq!(cull_at(&structure, UVec3::ZERO), Direction::RIGHT);
+}
+
+#[test]
+fn neighborhood_reads_adjacent_chunks() {
+    let size = UVec3::splat(2);
+    let center = Structure::uniform(size, Block::Stone);
+    let right = Structure::uniform(size, Block::Air);
+    let neighborhood = Neighborhood::gather(|offset| match offset.to_array() {
+        [0, 0, 0] => Some(&center),
+        [1, 0, 0] => Some(&right),
+        _ => None,
+    });
+    assert_eq!(neighborhood.get_block(IVec3::new(2, 1, 1)), Some(Block::Air));
+    assert_eq!(neighborhood.get_block(IVec3::new(-1, 0, 0)), None);
+
+    let index = center.linearize(UVec3::new(1, 0, 0)) as u64;
+    let cull = cull_faces(&neighborhood, [index].into_iter());
+    assert_eq!(cull, vec![(UVec3::new(1, 0, 0), Direction::RIGHT)]);
+}
+
+#[test]
+fn ao_matches_reference() {
+    let size = UVec3::new(4, 3, 5);
+    let solid = [
+        UVec3::new(1, 0, 1),
+        UVec3::new(2, 0, 1),
+        UVec3::new(1, 0, 2),
+        UVec3::new(1, 1, 1),
+        UVec3::new(2, 1, 2),
+        UVec3::new(3, 2, 4),
+        UVec3::new(0, 2, 3),
+        UVec3::new(1, 2, 3),
+        UVec3::new(0, 1, 4),
+    ];
+    let structure = fixture(size, &solid);
+    let is_solid = |position: IVec3| {
+        position.cmpge(IVec3::ZERO).all()
+            && position.cmplt(size.as_ivec3()).all()
+            && solid.contains(&position.as_uvec3())
+    };
+
+    for &voxel in &solid {
+        let ao = ao_at(&structure, voxel);
+        for (direction, normal) in SIDES {
+            let expected = reference_ao(&is_solid, voxel.as_ivec3(), normal);
+            let actual = ao[direction.bits().trailing_zeros() as usize];
+            assert!(
+                actual.abs_diff_eq(expected, 1e-6),
+                "{voxel} {direction:?}: {actual} != {expected}"
+            );
+        }
+    }
+}
+
+#[test]
+fn mesh_counts_visible_faces() {
+    let cases: [(&[UVec3], usize); 3] = [
+        (&[UVec3::ONE], 6),
+        (&[UVec3::ONE, UVec3::new(1, 1, 2)], 10),
+        (
+            &[
+                UVec3::new(1, 1, 1),
+                UVec3::new(2, 1, 1),
+                UVec3::new(1, 2, 1),
+                UVec3::new(2, 2, 1),
+                UVec3::new(1, 1, 2),
+                UVec3::new(2, 1, 2),
+                UVec3::new(1, 2, 2),
+                UVec3::new(2, 2, 2),
+            ],
+            24,
+        ),
+    ];
+    for (solid, faces) in cases {
+        let structure = fixture(UVec3::splat(4), solid);
+        let mesh = create_structure_mesh(&structure);
+        assert_eq!(mesh.count_vertices(), faces * 4);
+        assert_eq!(mesh.indices().unwrap().len(), faces * 6);
+    }
+}
+
+#[test]
+fn linearize_round_trips() {
+    for size in [
+        UVec3::new(1, 1, 1),
+        UVec3::new(3, 5, 7),
+        UVec3::new(7, 1, 2),
+        UVec3::new(2, 9, 1),
+        UVec3::new(34, 34, 34),
+    ] {
+        let structure = Structure::uniform(size, Block::Air);
+        assert_eq!(structure.count(), (size.x * size.y * size.z) as usize);
+        for index in 0..structure.count() {
+            let position = structure.delinearize(index);
+            assert!(position.cmplt(size).all(), "{size}: {position}");
+            assert_eq!(structure.linearize(position), index, "{size}");
+        }
+    }
+}
+
+#[test]
+fn graded_profile_respects_max_grade() {
+    let ground = [10, 10, 30, 30, 30, 10, 10, 10, 12, 10];
+    let distances = (0..ground.len()).map(|i| i as f32 * 2.0).collect::<Vec<_>>();
+    let graded = grade_profile(&ground, &distances, 0.5);
+    assert_eq!(graded.first(), ground.first());
+    assert_eq!(graded.last(), ground.last());
+    for pair in graded.windows(2) {
+        assert!((pair[1] - pair[0]).abs() <= 1, "{graded:?}");
+    }
+}
+
+#[test]
+fn turns_follow_heading() {
+    // Arriving from the south (+z) means heading north (-z); west (-x) is left.
+    let south = IVec2::new(0, 1);
+    assert_eq!(Junction::turn(south, IVec2::new(-1, 0)), Turn::Left);
+    assert_eq!(Junction::turn(south, IVec2::new(1, 0)), Turn::Right);
+    assert_eq!(Junction::turn(south, IVec2::new(0, -1)), Turn::Straight);
+    assert_eq!(Junction::turn(south, south), Turn::UTurn);
+}
+
+#[test]
+fn routes_avoid_restricted_turns() {
+    let east_west = (-10..=10).map(|x| IVec2::new(x, 0)).collect::<Vec<_>>();
+    let north_south = (-10..=10).map(|z| IVec2::new(0, z)).collect::<Vec<_>>();
+    let detour = (-10..=0)
+        .map(|z| IVec2::new(10, z))
+        .chain((0..10).map(|x| IVec2::new(x, -10)));
+    let mut network = RoadNetwork::default();
+    for column in east_west.iter().chain(&north_south).copied().chain(detour) {
+        network.insert(IVec3::new(column.x, 0, column.y));
+    }
+    network.add_segment(east_west);
+    network.add_segment(north_south);
+    let mut junctions = Junctions::default();
+    junctions.connect(&network, IVec3::ZERO, false);
+
+    // Arriving from the west and leaving to the north (-z) turns left.
+    let (west, north) = (IVec2::new(-10, 0), IVec2::new(0, -10));
+    let direct = junctions.route(&network, west, north).unwrap();
+    assert_eq!(Some(direct.clone()), network.route(west, north));
+    assert!(!direct.contains(&IVec2::new(10, 0)));
+
+    let junction = junctions.near_mut(IVec2::ZERO, 0).unwrap();
+    junction.restrictions = TurnRestrictions::NO_LEFT;
+    assert!(!junction.allows(IVec2::new(-1, 0), IVec2::new(0, -1)));
+    let around = junctions.route(&network, west, north).unwrap();
+    assert_eq!((around.first(), around.last()), (Some(&west), Some(&north)));
+    assert!(around.contains(&IVec2::new(10, 0)));
+    assert!(around.contains(&IVec2::new(10, -10)));
+}
+
+#[test]
+fn crossings_skip_grade_separated_roads() {
+    let mut network = RoadNetwork::default();
+    for x in -8..=8 {
+        network.insert(IVec3::new(x, 10, 0));
+    }
+    let road = |y: i32| (-8..=8).map(|z| IVec3::new(0, y, z)).collect::<Vec<_>>();
+    assert_eq!(crossings(&network, &road(11)), vec![IVec3::new(0, 11, 0)]);
+    assert!(crossings(&network, &road(20)).is_empty());
+}
+
+#[test]
+fn road_profile_cross_section() {
+    let samples = (0..16)
+        .map(|x| ProfileSample {
+            deck: IVec3::new(x, 10, 0),
+            ground: 10,
+            section: Section::Grade,
+            distance: x as f32,
+        })
+        .collect::<Vec<_>>();
+    let mut placed = bevy::utils::HashMap::new();
+    RoadProfile::default().place(&samples, |position, block| {
+        placed.insert(position, block);
+    });
+
+    let at = |z: i32, y: i32| placed.get(&IVec3::new(8, y, z)).copied();
+    assert_eq!(at(0, 10), Some(Block::Centerline));
+    assert_eq!(at(1, 10), Some(Block::Asphalt));
+    for side in [-1, 1] {
+        assert_eq!(at(3 * side, 10), Some(Block::LaneMarking));
+        assert_eq!(at(4 * side, 11), Some(Block::Curb));
+        assert_eq!(at(4 * side, 10), Some(Block::Stone));
+        assert_eq!(at(6 * side, 11), Some(Block::Sidewalk));
+        assert_eq!(at(7 * side, 11), None);
+    }
+
+    // Along a diagonal the centerline still joins through shared faces.
+    let diagonal = (0..16)
+        .map(|x| ProfileSample {
+            deck: IVec3::new(x, 10, x),
+            ..samples[x as usize]
+        })
+        .collect::<Vec<_>>();
+    let mut placed = bevy::utils::HashMap::new();
+    RoadProfile::default().place(&diagonal, |position, block| {
+        placed.insert(position, block);
+    });
+    for x in 1..16 {
+        let corners = [IVec3::new(x, 10, x - 1), IVec3::new(x - 1, 10, x)];
+        assert!(corners
+            .iter()
+            .any(|corner| placed.get(corner) == Some(&Block::Centerline)));
+    }
+}
+
+#[test]
+fn transit_line_loops_through_stops() {
+    let mut network = RoadNetwork::default();
+    for x in 0..=20 {
+        network.insert(IVec3::new(x, 10, 0));
+    }
+    let mut land_value = ScalarField2D::<LandValue>::default();
+    for z in -16..=16 {
+        for x in -16..=36 {
+            land_value.set(IVec2::new(x, z), 1.0);
+        }
+    }
+
+    let stops = [IVec2::new(0, 1), IVec2::new(20, -1)];
+    let mut line = TransitLine::new(TransitKind::Bus, &stops, &network).unwrap();
+    assert_eq!(line.stops, vec![IVec2::new(0, 0), IVec2::new(20, 0)]);
+    assert_eq!(line.route.first(), line.route.last());
+    assert_eq!(line.route.len(), 41);
+
+    for _ in 0..200 {
+        line.step(&land_value, 0.5);
+    }
+    assert!(line.ridership > 0);
+    for vehicle in &line.vehicles {
+        assert!(vehicle.passengers <= TransitKind::Bus.capacity());
+        assert!(network.contains(line.position(vehicle)));
+    }
+    assert!(TransitLine::new(TransitKind::Bus, &stops[..1], &network).is_err());
+    // Both stops snap to the same column, which would leave an empty route.
+    let crowded = [IVec2::new(5, 1), IVec2::new(5, -1)];
+    assert!(matches!(
+        TransitLine::new(TransitKind::Bus, &crowded, &network),
+        Err(TransitError::SharedStop(column)) if column == IVec2::new(5, 0)
+    ));
+}
+
+#[test]
+fn curve_checks_follow_network_limits() {
+    let straight = Line {
+        start: Vec3::ZERO,
+        end: Vec3::new(40.0, 0.0, 0.0),
+    };
+    let straight = check_curve(&straight, Limits::of::<Rail>());
+    assert!(straight.is_valid());
+    assert_eq!(straight.min_radius, f32::INFINITY);
+
+    let bend = Arc::new(
+        Vec3::new(12.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 12.0),
+        Vec3::new(-12.0, 0.0, 0.0),
+    );
+    let road = check_curve(&bend, Limits::of::<Road>());
+    assert!(road.is_valid(), "{:?}", road.error);
+    assert!((road.min_radius - 12.0).abs() < 0.5, "{}", road.min_radius);
+    let rail = check_curve(&bend, Limits::of::<Rail>());
+    assert!(matches!(rail.error, Some(CurveError::TooSharp { .. })));
+    assert!(rail.segments.iter().any(|segment| !segment.valid));
+
+    let steep = Line {
+        start: Vec3::ZERO,
+        end: Vec3::new(20.0, 5.0, 0.0),
+    };
+    let steep = check_curve(&steep, Limits::of::<Road>());
+    assert!(matches!(steep.error, Some(CurveError::TooSteep { .. })));
+
+    let crossing = CatmullRom {
+        points: vec![
+            Vec3::ZERO,
+            Vec3::new(20.0, 0.0, 0.0),
+            Vec3::new(20.0, 0.0, 20.0),
+            Vec3::new(10.0, 0.0, -10.0),
+        ],
+    };
+    let anything = Limits {
+        max_grade: 1.0,
+        min_radius: 0.0,
+    };
+    assert!(matches!(
+        check_curve(&crossing, anything).error,
+        Some(CurveError::SelfIntersecting { .. })
+    ));
+}
+
+#[test]
+fn curves_pass_through_their_points() {
+    let points = [
+        Vec3::ZERO,
+        Vec3::new(10.0, 2.0, 4.0),
+        Vec3::new(20.0, 0.0, -6.0),
+        Vec3::new(32.0, 4.0, 0.0),
+    ];
+    for shape in [
+        CurveShape::Straight,
+        CurveShape::Arc,
+        CurveShape::Bezier,
+        CurveShape::Spline,
+    ] {
+        let clicks = shape.clicks().unwrap_or(points.len());
+        let curve = shape.build(&points[..clicks]).unwrap();
+        assert!(curve.point(0.0).distance(points[0]) < 1e-3, "{shape:?}");
+        assert!(curve.point(1.0).distance(points[clicks - 1]) < 1e-3, "{shape:?}");
+        assert!(shape.build(&points[..1]).is_none(), "{shape:?}");
+    }
+    assert!(CurveShape::Arc.build(&points).is_none());
+    assert!(CurveShape::Straight.build(&points[..3]).is_none());
+
+    let arc = Arc::new(points[0], points[1], points[2]);
+    let through = (0..=100)
+        .map(|i| arc.point(i as f32 / 100.0).xz().distance(points[1].xz()))
+        .fold(f32::INFINITY, f32::min);
+    assert!(through < 0.5, "{through}");
+
+    // Uniform Catmull-Rom spans meet with matching tangents.
+    let spline = CatmullRom {
+        points: points.to_vec(),
+    };
+    let join = 1.0 / 3.0;
+    let before = spline.point(join) - spline.point(join - 1e-3);
+    let after = spline.point(join + 1e-3) - spline.point(join);
+    assert!(before.normalize().dot(after.normalize()) > 0.999, "{before} {after}");
+    assert!((before.length() / after.length() - 1.0).abs() < 0.1, "{before} {after}");
+
+    let measured = ArcLength::new(&spline);
+    assert!((measured.length() - spline.length()).abs() < 1e-3);
+    let spaced = measured.resample(1.0);
+    for pair in spaced.windows(2) {
+        assert!(pair[0].distance(pair[1]) <= 1.0 + 1e-3);
+    }
+}
+
+#[test]
+fn voxelized_curves_are_face_connected() {
+    let curves: [Box<dyn Curve>; 2] = [
+        Box::new(Line {
+            start: Vec3::ZERO,
+            end: Vec3::new(17.0, 0.0, 9.0),
+        }),
+        Box::new(Arc::new(
+            Vec3::ZERO,
+            Vec3::new(12.0, 0.0, 5.0),
+            Vec3::new(20.0, 0.0, 20.0),
+        )),
+    ];
+    for curve in &curves {
+        let voxels = voxelize(&**curve);
+        assert_eq!(voxels.first().unwrap().0, IVec3::ZERO);
+        let end = curve.point(1.0).round().as_ivec3();
+        assert_eq!(voxels.last().unwrap().0, end);
+
+        let mut seen = std::collections::HashSet::new();
+        for (i, &(voxel, distance)) in voxels.iter().enumerate() {
+            assert!(seen.insert(voxel), "{voxel} listed twice");
+            if i > 0 {
+                assert!(distance >= voxels[i - 1].1);
+                let touches = voxels[..i]
+                    .iter()
+                    .any(|(other, _)| (voxel - *other).abs().element_sum() == 1);
+                assert!(touches, "{voxel} has no face neighbor before it");
+            }
+        }
+    }
+}
+
+#[test]
+fn edits_refresh_faces_across_chunk_borders() {
+    let size = UVec3::splat(CHUNK_AXIS as u32);
+    let mut bevy_world = World::new();
+    let mut world = VoxelWorld::new(1);
+    for x in [0, 1] {
+        let chunk = Structure::uniform(size, Block::Air);
+        let heightmap = Heightmap::new(&chunk);
+        let entity = bevy_world.spawn((chunk, heightmap)).id();
+        world.mapping.insert(IVec3::new(x, 0, 0), entity);
+    }
+    bevy_world.insert_resource(world);
+
+    // Two blocks facing each other across the border between the chunks.
+    let last = CHUNK_AXIS as i32 - 1;
+    let left = IVec3::new(last, 4, 4);
+    let mut edit = WorldEdit::new();
+    edit.set(left, Block::Stone);
+    edit.set(left + IVec3::X, Block::Stone);
+    edit.apply(&mut bevy_world);
+
+    let world = bevy_world.resource::<VoxelWorld>();
+    let chunks = [0, 1].map(|x| world.chunk_entity(IVec3::new(x, 0, 0)).unwrap());
+    let structure = |i: usize| bevy_world.get::<Structure>(chunks[i]).unwrap();
+    let local = UVec3::new(last as u32, 4, 4);
+    assert_eq!(
+        cull_at(structure(0), local),
+        Direction::all() - Direction::RIGHT
+    );
+    assert_eq!(
+        cull_at(structure(1), UVec3::new(0, 4, 4)),
+        Direction::all() - Direction::LEFT
+    );
+    for chunk in chunks {
+        assert!(bevy_world.get::<Dirty>(chunk).is_some());
+    }
+}
+
+#[test]
+fn edits_to_ungenerated_chunks_wait_for_them() {
+    let mut bevy_world = World::new();
+    bevy_world.insert_resource(VoxelWorld::new(1));
+    let mut edit = WorldEdit::new();
+    edit.set(IVec3::new(40, 2, -3), Block::Stone)
+        .set(IVec3::new(41, 2, -3), Block::Asphalt)
+        .set(IVec3::new(40, 2, -3), Block::Rail);
+    assert_eq!(edit.apply(&mut bevy_world), 1);
+
+    let mut world = bevy_world.resource_mut::<VoxelWorld>();
+    assert_eq!(world.deferred_chunks(), 1);
+    let size = UVec3::splat(CHUNK_AXIS as u32);
+    let mut chunk = Structure::uniform(size, Block::Air);
+    world.restore_edits(IVec3::new(1, 0, -1), &mut chunk);
+    assert_eq!(world.deferred_chunks(), 0);
+
+    let local = [UVec3::new(8, 2, 29), UVec3::new(9, 2, 29)];
+    let blocks = chunk.get_block(local).collect::<Vec<_>>();
+    assert_eq!(blocks, [Block::Rail, Block::Asphalt]);
+    assert!(cull_at(&chunk, local[0]).contains(Direction::UP));
+}
+
+#[test]
+fn column_queries_follow_edits() {
+    let axis = CHUNK_AXIS as u32;
+    let size = UVec3::splat(axis);
+    let mut ground = Structure::uniform(size, Block::Air);
+    let layers =
+        (0..axis * axis * 5).map(|i| UVec3::new(i % axis, i / axis / axis, i / axis % axis));
+    ground.set_block(layers.map(|position| (position, Block::Stone)));
+
+    let mut bevy_world = World::new();
+    let mut world = VoxelWorld::new(1);
+    world.columns.insert(IVec2::ZERO, (0, 0));
+    for (y, chunk) in [
+        (-1, Structure::uniform(size, Block::Stone)),
+        (0, ground),
+        (1, Structure::uniform(size, Block::Air)),
+    ] {
+        let heightmap = Heightmap::new(&chunk);
+        let entity = bevy_world.spawn((chunk, heightmap)).id();
+        world.mapping.insert(IVec3::new(0, y, 0), entity);
+    }
+    bevy_world.insert_resource(world);
+
+    let column = IVec2::new(3, 3);
+    let at = |y: i32| IVec3::new(3, y, 3);
+    assert_eq!(surface_height(&bevy_world, column), Ok(5));
+    assert_eq!(solid_below(&bevy_world, at(40)), Ok(at(4)));
+    assert_eq!(solid_below(&bevy_world, at(-5)), Ok(at(-5)));
+    assert_eq!(get_ground_level(&mut bevy_world, at(0)), Ok(5));
+    assert!(surface_height(&bevy_world, IVec2::new(40, 3)).is_err());
+
+    set_block(&mut bevy_world, at(40), Block::Stone);
+    assert_eq!(surface_height(&bevy_world, column), Ok(41));
+    assert_eq!(solid_below(&bevy_world, at(39)), Ok(at(4)));
+    let heights = heightmap(&bevy_world, IVec2::new(2, 3), IVec2::new(3, 4));
+    assert_eq!(heights, Ok(vec![5, 41, 5, 5]));
+}
+
+#[test]
+fn edits_refresh_faces_around_them() {
+    let center = UVec3::splat(2);
+    let above = center + UVec3::Y;
+    let mut structure = Structure::uniform(UVec3::splat(5), Block::Air);
+    structure.set_block([(center, Block::Stone)]);
+    refresh_surface(&mut structure, [center]);
+    assert_eq!(cull_at(&structure, center), Direction::all());
+
+    structure.set_block([(above, Block::Stone)]);
+    refresh_surface(&mut structure, [above]);
+    assert_eq!(
+        cull_at(&structure, center),
+        Direction::all() - Direction::UP
+    );
+    assert_eq!(
+        cull_at(&structure, above),
+        Direction::all() - Direction::DOWN
+    );
+
+    structure.set_block([(above, Block::Air)]);
+    refresh_surface(&mut structure, [above]);
+    assert_eq!(cull_at(&structure, center), Direction::all());
+
+    // Digging into solid ground exposes the faces around the hole.
+    let mut ground = Structure::uniform(UVec3::splat(5), Block::Stone);
+    ground.set_block([(center, Block::Air)]);
+    refresh_surface(&mut ground, [center]);
+    assert_eq!(cull_at(&ground, center + UVec3::X), Direction::LEFT);
+    assert_eq!(cull_at(&ground, above), Direction::DOWN);
+    assert_eq!(cull_at(&ground, UVec3::ZERO), Direction::empty());
+}
+
+#[test]
+fn saves_load_back() {
+    let size = UVec3::splat(CHUNK_AXIS as u32);
+    let (stone, rail) = (IVec3::new(1, 2, 3), IVec3::new(4, 5, 6));
+    let mut ground = Structure::uniform(size, Block::Air);
+    ground.set_block([
+        (stone.as_uvec3(), Block::Stone),
+        (rail.as_uvec3(), Block::Rail),
+    ]);
+    let mut bevy_world = World::new();
+    bevy_world.insert_resource(Terrain { seed: 7 });
+    let mut world = VoxelWorld::new(1);
+    for (position, chunk) in [
+        (IVec3::ZERO, ground),
+        (IVec3::Y, Structure::uniform(size, Block::Air)),
+    ] {
+        let entity = bevy_world.spawn((chunk, Chunk(position))).id();
+        world.mapping.insert(position, entity);
+    }
+    bevy_world.insert_resource(world);
+
+    let directory = std::env::temp_dir();
+    let name = |version: u32| format!("xenotech-{}-v{version}.xtwd", std::process::id());
+    let (v1, v2) = (directory.join(name(1)), directory.join(name(2)));
+    // A version 1 save is a version 2 save without the deferred edit count.
+    save_world(&mut bevy_world, &v1).unwrap();
+    let mut bytes = std::fs::read(&v1).unwrap();
+    bytes.truncate(bytes.len() - 4);
+    bytes[4..8].copy_from_slice(&1u32.to_le_bytes());
+    std::fs::write(&v1, bytes).unwrap();
+
+    let mut edit = WorldEdit::new();
+    edit.set(IVec3::new(40, 2, -3), Block::Asphalt);
+    edit.apply(&mut bevy_world);
+    save_world(&mut bevy_world, &v2).unwrap();
+
+    let queued = [(UVec3::new(8, 2, 29), Block::Asphalt)];
+    for (path, deferred) in [(v1, None), (v2, Some(&queued[..]))] {
+        let mut loaded = World::new();
+        loaded.insert_resource(VoxelWorld::new(1));
+        load_world(&mut loaded, &path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.resource::<Terrain>().seed, 7);
+        assert_eq!(loaded.resource::<VoxelWorld>().chunk_count(), 2);
+        assert_eq!(get_block(&mut loaded, stone), Some(Block::Stone));
+        assert_eq!(get_block(&mut loaded, rail), Some(Block::Rail));
+        assert_eq!(get_block(&mut loaded, IVec3::Y * 40), Some(Block::Air));
+        let world = loaded.resource::<VoxelWorld>();
+        let edits = world.deferred.get(&IVec3::new(1, 0, -1));
+        assert_eq!(edits.map(Vec::as_slice), deferred);
+        let chunk = loaded.get::<Structure>(world.chunk_entity(IVec3::ZERO).unwrap());
+        assert_eq!(cull_at(chunk.unwrap(), stone.as_uvec3()), Direction::all());
+    }
+}
+
+#[test]
+fn generated_height_matches_generated_chunks() {
+    let axis = CHUNK_AXIS as i32;
+    let voxel = |position: IVec3| {
+        let chunk = gen_chunk(DEFAULT_SEED, position.div_euclid(IVec3::splat(axis)));
+        let local = position.rem_euclid(IVec3::splat(axis)).as_uvec3();
+        chunk.get_block([local]).next().unwrap()
+    };
+    for column in [IVec2::ZERO, IVec2::new(45, -70), IVec2::new(-300, 129)] {
+        let height = generated_height(DEFAULT_SEED, column);
+        let top = IVec3::new(column.x, height, column.y);
+        assert_eq!(voxel(top), Block::Air, "{column}");
+        assert_ne!(voxel(top - IVec3::Y), Block::Air, "{column}");
+    }
+}
+
+#[test]
+fn palette_covers_every_block() {
+    for (id, &block) in Block::ALL.iter().enumerate() {
+        assert_eq!(block as usize, id);
+        let placeable = !matches!(block, Block::Void | Block::Air);
+        assert_eq!(Block::PALETTE.contains(&block), placeable, "{block:?}");
+    }
+}
+
+#[test]
+fn solid_chunks_show_faces_toward_air() {
+    let size = UVec3::splat(CHUNK_AXIS as u32);
+    let consolidated = |above: Block| {
+        let mut bevy_world = World::new();
+        let mut world = VoxelWorld::new(1);
+        all_neighbors(IVec3::ZERO, |position| {
+            let block = [Block::Stone, above][(position == IVec3::Y) as usize];
+            let chunk = Structure::uniform(size, block);
+            let entity = bevy_world.spawn((chunk, Chunk(position), Dirty)).id();
+            world.mapping.insert(position, entity);
+        });
+        let center = world.chunk_entity(IVec3::ZERO).unwrap();
+        bevy_world.insert_resource(world);
+        consolidate(&mut bevy_world);
+        assert!(!bevy_world.entity(center).contains::<Dirty>());
+        bevy_world.entity_mut(center).take::<Structure>().unwrap()
+    };
+
+    let buried = consolidated(Block::Stone);
+    assert!(!buried.has_visible_faces());
+
+    let exposed = consolidated(Block::Air);
+    assert!(exposed.has_visible_faces());
+    assert_eq!(exposed.uniform_block(), Some(Block::Stone));
+    let top = size.y - 1;
+    assert_eq!(cull_at(&exposed, UVec3::new(5, top, 7)), Direction::UP);
+    assert!(cull_at(&exposed, UVec3::new(5, top - 1, 7)).is_empty());
+    assert!(cull_at(&exposed, UVec3::ZERO).is_empty());
+}
+
+#[test]
+fn service_coverage_raises_land_value() {
+    let (covered, uncovered) = (IVec2::new(30, 2), IVec2::new(30, 60));
+    let fields_after = |updates: usize| {
+        let mut network = RoadNetwork::default();
+        for x in 0..=40 {
+            network.insert(IVec3::new(x, 10, 0));
+        }
+        let mut bevy_world = World::new();
+        bevy_world.insert_resource(network);
+        bevy_world.init_resource::<ServiceCoverage>();
+        bevy_world.init_resource::<ScalarField2D<LandValue>>();
+        bevy_world.init_resource::<ScalarField2D<Happiness>>();
+        bevy_world.spawn(ServiceBuilding {
+            kind: ServiceKind::School,
+            position: IVec3::new(0, 10, 3),
+        });
+        let mut schedule = Schedule::default();
+        schedule.add_systems(update_coverage);
+        for _ in 0..updates {
+            bevy_world.resource_mut::<ServiceCoverage>().mark_dirty();
+            schedule.run(&mut bevy_world);
+        }
+        let coverage = bevy_world.resource::<ServiceCoverage>();
+        assert!(coverage.land_value(covered) > 0.0);
+        assert_eq!(coverage.land_value(uncovered), 0.0);
+
+        let mut land_value = bevy_world
+            .remove_resource::<ScalarField2D<LandValue>>()
+            .unwrap();
+        let mut happiness = bevy_world
+            .remove_resource::<ScalarField2D<Happiness>>()
+            .unwrap();
+        for _ in 0..50 {
+            land_value.step(0.1);
+            happiness.step(0.1);
+        }
+        (land_value, happiness)
+    };
+
+    let (land_value, happiness) = fields_after(1);
+    assert!(land_value.get(covered) > land_value.get(uncovered));
+    assert!(happiness.get(covered) > happiness.get(uncovered));
+    // Recomputing the coverage replaces its sources instead of adding more.
+    let (recomputed, _) = fields_after(3);
+    assert!((recomputed.get(covered) - land_value.get(covered)).abs() < 1e-4);
+}
+
+#[test]
+fn diagonal_rays_hit_a_single_face() {
+    let size = UVec3::splat(CHUNK_AXIS as u32);
+    let mut chunk = Structure::uniform(size, Block::Air);
+    let stone = [UVec3::new(3, 3, 0), UVec3::new(8, 0, 0)];
+    chunk.set_block(stone.map(|position| (position, Block::Stone)));
+    let mut bevy_world = World::new();
+    let mut world = VoxelWorld::new(1);
+    let entity = bevy_world.spawn(chunk).id();
+    world.mapping.insert(IVec3::ZERO, entity);
+    bevy_world.insert_resource(world);
+
+    // A 45° ray passes exactly through voxel edges on its way to (3, 3, 0).
+    let ray = Ray {
+        origin: Vec3::new(0.5, 0.5, 0.5),
+        direction: Vec3::new(1.0, 1.0, 0.0),
+    };
+    let hit = ray.intersect_voxels(&bevy_world).unwrap();
+    assert_eq!(hit.voxel, IVec3::new(3, 3, 0));
+    assert_eq!(hit.normal.abs().element_sum(), 1);
+    assert_eq!(hit.face, Direction::from_normal(hit.normal));
+    assert!(!hit.face.is_empty());
+    assert_eq!(get_block(&mut bevy_world, hit.adjacent()), Some(Block::Air));
+
+    // Starting inside a voxel, the normal comes from the dominant axis alone.
+    let ray = Ray {
+        origin: Vec3::new(8.5, 0.5, 0.5),
+        direction: Vec3::new(1.0, 1.0, 1.0),
+    };
+    let hit = ray.intersect_voxels(&bevy_world).unwrap();
+    assert_eq!(hit.voxel, IVec3::new(8, 0, 0));
+    assert_eq!(hit.normal, IVec3::NEG_X);
+    assert_eq!(hit.face, Direction::LEFT);
+}
@@ -0,0 +1,312 @@
+use std::fmt;
+
+use bevy::prelude::*;
+
+use crate::fields::LandValue;
+use crate::fields::ScalarField2D;
+use crate::network::Network;
+use crate::network::NetworkKind;
+use crate::network::RailNetwork;
+use crate::network::RoadNetwork;
+
+/// How far a clicked stop may be from the network it snaps to.
+const STOP_SNAP: i32 = 4;
+/// Half size of the square around a stop that its passengers come from.
+const CATCHMENT: i32 = 12;
+/// Passengers per second generated at a stop per unit of land value in its
+/// catchment. Land value stands in for residents and jobs until citizens are
+/// simulated individually.
+const DEMAND_RATE: f32 = 0.5;
+/// Share of the passengers on board that leave at each stop.
+const ALIGHT_SHARE: f32 = 0.3;
+/// Seconds a vehicle waits at a stop.
+const DWELL: f32 = 2.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TransitKind {
+    Bus,
+    Tram,
+    Rail,
+}
+
+impl TransitKind {
+    pub const ALL: [TransitKind; 3] = [TransitKind::Bus, TransitKind::Tram, TransitKind::Rail];
+
+    /// Voxels travelled per second.
+    pub fn speed(self) -> f32 {
+        match self {
+            TransitKind::Bus => 6.0,
+            TransitKind::Tram => 8.0,
+            TransitKind::Rail => 16.0,
+        }
+    }
+
+    pub fn capacity(self) -> u32 {
+        match self {
+            TransitKind::Bus => 40,
+            TransitKind::Tram => 120,
+            TransitKind::Rail => 400,
+        }
+    }
+
+    /// Route length served by each vehicle on the line.
+    fn spacing(self) -> f32 {
+        match self {
+            TransitKind::Bus => 96.0,
+            TransitKind::Tram => 128.0,
+            TransitKind::Rail => 256.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TransitError {
+    TooFewStops,
+    OffNetwork(IVec2),
+    SharedStop(IVec2),
+    Unreachable { from: IVec2, to: IVec2 },
+}
+
+impl fmt::Display for TransitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransitError::TooFewStops => write!(f, "a line needs at least two stops"),
+            TransitError::OffNetwork(column) => write!(f, "stop at {column} is not on the network"),
+            TransitError::SharedStop(column) => {
+                write!(f, "consecutive stops share the network column {column}")
+            }
+            TransitError::Unreachable { from, to } => {
+                write!(f, "no connection from {from} to {to}")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Vehicle {
+    /// Distance travelled along the route, wrapping at its length.
+    pub distance: f32,
+    pub passengers: u32,
+    /// Index of the next stop to serve.
+    next_stop: usize,
+    dwell: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct TransitLine {
+    pub kind: TransitKind,
+    /// Stops in service order, snapped to the network. The line loops from
+    /// the last stop back to the first.
+    pub stops: Vec<IVec2>,
+    /// Network columns visited in order around the loop.
+    pub route: Vec<IVec2>,
+    /// Distance along the route of each stop.
+    stop_distances: Vec<f32>,
+    length: f32,
+    pub vehicles: Vec<Vehicle>,
+    /// Passengers waiting at each stop.
+    pub waiting: Vec<f32>,
+    /// Boardings since the line opened.
+    pub ridership: u64,
+}
+
+impl TransitLine {
+    pub fn new<K: NetworkKind>(
+        kind: TransitKind,
+        stops: &[IVec2],
+        network: &Network<K>,
+    ) -> Result<Self, TransitError> {
+        if stops.len() < 2 {
+            return Err(TransitError::TooFewStops);
+        }
+        let stops = stops
+            .iter()
+            .map(|&stop| {
+                network
+                    .nearest(stop, STOP_SNAP)
+                    .ok_or(TransitError::OffNetwork(stop))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // The loop runs from the last stop back to the first, so that pair
+        // has to be distinct too.
+        for (i, &stop) in stops.iter().enumerate() {
+            if stops[(i + 1) % stops.len()] == stop {
+                return Err(TransitError::SharedStop(stop));
+            }
+        }
+
+        let mut route = vec![stops[0]];
+        let mut stop_distances = vec![];
+        let mut length = 0.0;
+        for (i, &from) in stops.iter().enumerate() {
+            let to = stops[(i + 1) % stops.len()];
+            stop_distances.push(length);
+            let leg = network
+                .route(from, to)
+                .ok_or(TransitError::Unreachable { from, to })?;
+            for pair in leg.windows(2) {
+                length += (pair[1] - pair[0]).as_vec2().length();
+                route.push(pair[1]);
+            }
+        }
+
+        let count = (length / kind.spacing()).ceil().max(1.0) as usize;
+        let vehicles = (0..count)
+            .map(|i| {
+                let distance = length * i as f32 / count as f32;
+                Vehicle {
+                    distance,
+                    passengers: 0,
+                    next_stop: stop_distances
+                        .iter()
+                        .position(|&stop| stop >= distance)
+                        .unwrap_or(0),
+                    dwell: 0.0,
+                }
+            })
+            .collect();
+
+        Ok(TransitLine {
+            kind,
+            waiting: vec![0.0; stops.len()],
+            stops,
+            route,
+            stop_distances,
+            length,
+            vehicles,
+            ridership: 0,
+        })
+    }
+
+    /// Column a vehicle currently occupies.
+    pub fn position(&self, vehicle: &Vehicle) -> IVec2 {
+        let mut travelled = 0.0;
+        for pair in self.route.windows(2) {
+            travelled += (pair[1] - pair[0]).as_vec2().length();
+            if travelled > vehicle.distance {
+                return pair[0];
+            }
+        }
+        self.route[0]
+    }
+
+    /// Generates passengers at every stop and moves vehicles along the
+    /// route, exchanging passengers at the stops they reach.
+    pub fn step(&mut self, land_value: &ScalarField2D<LandValue>, dt: f32) {
+        for (stop, waiting) in self.stops.iter().zip(&mut self.waiting) {
+            let value = land_value.average(*stop - CATCHMENT, *stop + CATCHMENT);
+            *waiting += value.max(0.0) * DEMAND_RATE * dt;
+        }
+
+        let capacity = self.kind.capacity();
+        for vehicle in &mut self.vehicles {
+            if vehicle.dwell > 0.0 {
+                vehicle.dwell -= dt;
+                continue;
+            }
+            let target = self.stop_distances[vehicle.next_stop];
+            let wraps = vehicle.next_stop == 0 && vehicle.distance > target;
+            let remaining = if wraps {
+                self.length - vehicle.distance + target
+            } else {
+                target - vehicle.distance
+            };
+            let travel = self.kind.speed() * dt;
+            if travel < remaining {
+                vehicle.distance = (vehicle.distance + travel) % self.length;
+                continue;
+            }
+
+            vehicle.distance = target;
+            let alighting = (vehicle.passengers as f32 * ALIGHT_SHARE).ceil() as u32;
+            vehicle.passengers -= alighting.min(vehicle.passengers);
+            let waiting = &mut self.waiting[vehicle.next_stop];
+            let boarding = (waiting.floor() as u32).min(capacity - vehicle.passengers);
+            *waiting -= boarding as f32;
+            vehicle.passengers += boarding;
+            self.ridership += boarding as u64;
+            vehicle.dwell = DWELL;
+            vehicle.next_stop = (vehicle.next_stop + 1) % self.stops.len();
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct TransitLines {
+    pub lines: Vec<TransitLine>,
+}
+
+impl TransitLines {
+    pub fn ridership(&self, kind: TransitKind) -> u64 {
+        self.lines
+            .iter()
+            .filter(|line| line.kind == kind)
+            .map(|line| line.ridership)
+            .sum()
+    }
+}
+
+/// Line being laid out: 5, 6 and 7 pick bus, tram or rail, clicks add stops,
+/// Enter opens the line and Escape discards it.
+#[derive(Resource, Default)]
+pub struct TransitTool {
+    pub selected: Option<TransitKind>,
+    pub stops: Vec<IVec2>,
+}
+
+pub fn transit_tool_input(keys: Res<Input<KeyCode>>, mut tool: ResMut<TransitTool>) {
+    let selection = [KeyCode::Key5, KeyCode::Key6, KeyCode::Key7]
+        .into_iter()
+        .zip(TransitKind::ALL)
+        .find(|(key, _)| keys.just_pressed(*key));
+    if let Some((_, kind)) = selection {
+        tool.selected = Some(kind);
+        tool.stops.clear();
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        tool.selected = None;
+        tool.stops.clear();
+    }
+}
+
+/// Opens the line being laid out by the transit tool once Enter is pressed.
+pub fn open_line(
+    keys: Res<Input<KeyCode>>,
+    mut tool: ResMut<TransitTool>,
+    mut lines: ResMut<TransitLines>,
+    roads: Res<RoadNetwork>,
+    rails: Res<RailNetwork>,
+) {
+    if !keys.just_pressed(KeyCode::Return) {
+        return;
+    }
+    let Some(kind) = tool.selected else {
+        return;
+    };
+    let stops = std::mem::take(&mut tool.stops);
+    let line = match kind {
+        TransitKind::Bus | TransitKind::Tram => TransitLine::new(kind, &stops, &*roads),
+        TransitKind::Rail => TransitLine::new(kind, &stops, &*rails),
+    };
+    match line {
+        Ok(line) => {
+            info!(
+                "{kind:?} line opened: {} stops, {} vehicles",
+                line.stops.len(),
+                line.vehicles.len()
+            );
+            lines.lines.push(line);
+        }
+        Err(error) => warn!("transit line rejected: {error}"),
+    }
+}
+
+pub fn step_transit(
+    mut lines: ResMut<TransitLines>,
+    land_value: Res<ScalarField2D<LandValue>>,
+    time: Res<Time>,
+) {
+    for line in &mut lines.lines {
+        line.step(&land_value, time.delta_seconds());
+    }
+}
@@ -0,0 +1,197 @@
+use std::fmt;
+
+use bevy::prelude::*;
+use bevy::utils::hashbrown::HashMap;
+
+use crate::curve::ArcLength;
+use crate::curve::Curve;
+use crate::network::NetworkKind;
+
+/// Arc length between the points the curve is resampled to before measuring
+/// it.
+const SAMPLE_SPACING: f32 = 1.0;
+/// Samples on each side of a point used to measure the turning radius, so
+/// that voxel-sized wiggles do not read as sharp turns.
+const RADIUS_STRIDE: usize = 3;
+/// Horizontal distance over which the grade is measured.
+const GRADE_WINDOW: usize = 4;
+/// Side of the grid cells that segments are binned into, so that only
+/// segments sharing a cell are tested for crossings.
+const CROSSING_CELL: f32 = 4.0;
+
+/// Geometric limits a placed curve must stay within.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Limits {
+    pub max_grade: f32,
+    pub min_radius: f32,
+}
+
+impl Limits {
+    pub fn of<K: NetworkKind>() -> Self {
+        Limits {
+            max_grade: K::MAX_GRADE,
+            min_radius: K::MIN_RADIUS,
+        }
+    }
+}
+
+/// Measurements of one resampled stretch of a curve.
+#[derive(Clone, Copy, Debug)]
+pub struct SegmentCheck {
+    pub start: Vec3,
+    pub end: Vec3,
+    /// Turning radius through this segment; infinite on straights.
+    pub radius: f32,
+    pub grade: f32,
+    pub valid: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CurveError {
+    TooSharp { at: IVec3, radius: f32, min: f32 },
+    TooSteep { at: IVec3, grade: f32, max: f32 },
+    SelfIntersecting { at: IVec3 },
+}
+
+impl fmt::Display for CurveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CurveError::TooSharp { at, radius, min } => {
+                write!(f, "curve at {at} has radius {radius:.1} (min {min})")
+            }
+            CurveError::TooSteep { at, grade, max } => {
+                write!(f, "curve at {at} has grade {grade:.2} (max {max})")
+            }
+            CurveError::SelfIntersecting { at } => write!(f, "curve crosses itself at {at}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CurveReport {
+    pub segments: Vec<SegmentCheck>,
+    pub min_radius: f32,
+    pub max_grade: f32,
+    pub error: Option<CurveError>,
+}
+
+impl CurveReport {
+    pub fn is_valid(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Radius of the circle through three points, in the horizontal plane.
+fn circumradius(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    let area = (b - a).perp_dot(c - a).abs() / 2.0;
+    if area < 1e-4 {
+        return f32::INFINITY;
+    }
+    a.distance(b) * b.distance(c) * c.distance(a) / (4.0 * area)
+}
+
+/// Grid cells covered by the bounding box of the segment from `a` to `b`.
+fn crossing_cells(a: Vec2, b: Vec2) -> impl Iterator<Item = IVec2> {
+    let min = (a.min(b) / CROSSING_CELL).floor().as_ivec2();
+    let max = (a.max(b) / CROSSING_CELL).floor().as_ivec2();
+    (min.x..=max.x).flat_map(move |x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+}
+
+fn segments_cross(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> bool {
+    let side = |p: Vec2, q: Vec2, r: Vec2| (q - p).perp_dot(r - p);
+    side(a, b, c) * side(a, b, d) < 0.0 && side(c, d, a) * side(c, d, b) < 0.0
+}
+
+/// Measures the turning radius and grade along `curve`, sampled evenly by
+/// arc length, against `limits`.
+pub fn check_curve(curve: &dyn Curve, limits: Limits) -> CurveReport {
+    let points = ArcLength::new(curve).resample(SAMPLE_SPACING);
+    let n = points.len();
+    let mut report = CurveReport {
+        segments: vec![],
+        min_radius: f32::INFINITY,
+        max_grade: 0.0,
+        error: None,
+    };
+    for i in 0..n.saturating_sub(1) {
+        let radius = if i >= RADIUS_STRIDE && i + RADIUS_STRIDE < n {
+            circumradius(
+                points[i - RADIUS_STRIDE].xz(),
+                points[i].xz(),
+                points[i + RADIUS_STRIDE].xz(),
+            )
+        } else {
+            f32::INFINITY
+        };
+        let (low, high) = (
+            i.saturating_sub(GRADE_WINDOW / 2),
+            (i + GRADE_WINDOW / 2).min(n - 1),
+        );
+        let run = points[low].xz().distance(points[high].xz());
+        let grade = if run > 0.0 {
+            (points[high].y - points[low].y).abs() / run
+        } else {
+            0.0
+        };
+
+        let at = points[i].round().as_ivec3();
+        let error = if radius < limits.min_radius {
+            Some(CurveError::TooSharp {
+                at,
+                radius,
+                min: limits.min_radius,
+            })
+        } else if grade > limits.max_grade {
+            Some(CurveError::TooSteep {
+                at,
+                grade,
+                max: limits.max_grade,
+            })
+        } else {
+            None
+        };
+        report.min_radius = report.min_radius.min(radius);
+        report.max_grade = report.max_grade.max(grade);
+        report.error = report.error.or(error);
+        report.segments.push(SegmentCheck {
+            start: points[i],
+            end: points[i + 1],
+            radius,
+            grade,
+            valid: error.is_none(),
+        });
+    }
+
+    // Two segments can only cross where their bounding boxes overlap, so each
+    // one is tested against the earlier segments sharing one of its cells.
+    let mut grid = HashMap::<IVec2, Vec<usize>>::new();
+    let mut crossings = vec![];
+    for (j, b) in report.segments.iter().enumerate() {
+        let (c, d) = (b.start.xz(), b.end.xz());
+        let mut candidates = crossing_cells(c, d)
+            .filter_map(|cell| grid.get(&cell))
+            .flatten()
+            .copied()
+            .filter(|&i| i + 2 <= j)
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+        candidates.dedup();
+        for i in candidates {
+            let a = &report.segments[i];
+            if segments_cross(a.start.xz(), a.end.xz(), c, d) {
+                crossings.push((i, j));
+            }
+        }
+        for cell in crossing_cells(c, d) {
+            grid.entry(cell).or_default().push(j);
+        }
+    }
+    crossings.sort_unstable();
+    for (i, j) in crossings {
+        let at = report.segments[j].start.round().as_ivec3();
+        report.error = report.error.or(Some(CurveError::SelfIntersecting { at }));
+        report.segments[i].valid = false;
+        report.segments[j].valid = false;
+    }
+    report
+}
@@ -0,0 +1,523 @@
+use std::cmp::Reverse;
+use std::iter;
+
+use bevy::core::TaskPoolThreadAssignmentPolicy;
+use bevy::ecs::system::SystemState;
+use bevy::math::Affine3A;
+use bevy::prelude::*;
+use bevy::render::primitives::Aabb;
+use bevy::render::primitives::Frustum;
+use bevy::tasks;
+use bevy::tasks::AsyncComputeTaskPool;
+use bevy::tasks::Task;
+use bevy::tasks::TaskPoolBuilder;
+use bevy::utils::hashbrown::HashMap;
+use bevy::utils::hashbrown::HashSet;
+use bevy::utils::FloatOrd;
+
+use crate::column::ColumnError;
+use crate::column::Heightmap;
+use crate::structure::all_neighbors;
+use crate::structure::ao_faces;
+use crate::structure::border_indices;
+use crate::structure::calc_ao;
+use crate::structure::calc_cull;
+use crate::structure::cull_faces;
+use crate::structure::refresh_surface;
+use crate::structure::Neighborhood;
+use crate::terrain::column_range;
+use crate::terrain::gen_chunk;
+use crate::terrain::terrain_noise;
+use crate::terrain::Terrain;
+use crate::Block;
+use crate::Structure;
+use crate::CHUNK_AXIS;
+
+/// Streams chunks in around the camera (or the origin when there is none),
+/// generates them on the async compute pool and keeps their borders
+/// consolidated with their neighbors.
+pub struct VoxelWorldPlugin {
+    pub view: usize,
+}
+
+impl Default for VoxelWorldPlugin {
+    fn default() -> Self {
+        VoxelWorldPlugin { view: 12 }
+    }
+}
+
+impl Plugin for VoxelWorldPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(VoxelWorld::new(self.view))
+            .add_systems(Update, load)
+            .add_systems(Update, (spawn, apply_deferred, consolidate).chain());
+    }
+}
+
+/// Task pool settings that leave one core free for the main schedule and
+/// give the rest to chunk generation.
+pub fn task_pool_plugin() -> TaskPoolPlugin {
+    let thread_count = chunk_thread_count();
+    TaskPoolPlugin {
+        task_pool_options: TaskPoolOptions {
+            async_compute: TaskPoolThreadAssignmentPolicy {
+                min_threads: thread_count,
+                max_threads: thread_count,
+                percent: 1.0,
+            },
+            ..default()
+        },
+    }
+}
+
+#[derive(Component)]
+pub struct Dirty;
+
+#[derive(Component)]
+pub struct Active;
+
+#[derive(Component)]
+pub struct Chunk(pub IVec3);
+
+#[derive(Resource)]
+pub struct VoxelWorld {
+    pub view: usize,
+    pub(crate) origin: IVec3,
+    pub(crate) loaded: HashSet<IVec3>,
+    pub(crate) mapping: HashMap<IVec3, Entity>,
+    pub(crate) pending: Vec<IVec3>,
+    pub(crate) columns: HashMap<IVec2, (i32, i32)>,
+    pub(crate) chunk_futures: HashMap<IVec3, Task<Structure>>,
+    /// Writes to chunks that have not been generated yet, in order, applied
+    /// by `spawn` once they are.
+    pub(crate) deferred: HashMap<IVec3, Vec<(UVec3, Block)>>,
+}
+
+impl VoxelWorld {
+    const UNSET_ORIGIN: IVec3 = IVec3::new(i32::MAX, 0, 0);
+
+    pub fn new(view: usize) -> Self {
+        VoxelWorld {
+            view,
+            origin: Self::UNSET_ORIGIN,
+            loaded: HashSet::new(),
+            mapping: HashMap::new(),
+            pending: Vec::new(),
+            columns: HashMap::new(),
+            chunk_futures: HashMap::new(),
+            deferred: HashMap::new(),
+        }
+    }
+
+    /// Chunk the load origin currently sits in.
+    pub fn origin(&self) -> IVec3 {
+        self.origin
+    }
+
+    pub fn chunk_entity(&self, position: IVec3) -> Option<Entity> {
+        self.mapping.get(&position).copied()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.mapping.len()
+    }
+
+    /// True once every chunk needed around the origin has been generated.
+    pub fn is_settled(&self) -> bool {
+        self.origin != Self::UNSET_ORIGIN
+            && self.pending.is_empty()
+            && self.chunk_futures.is_empty()
+    }
+
+    /// Chunks with edits waiting for them to be generated.
+    pub fn deferred_chunks(&self) -> usize {
+        self.deferred.len()
+    }
+
+    /// Applies the edits queued for the chunk at `position` to its freshly
+    /// generated `chunk`.
+    pub(crate) fn restore_edits(&mut self, position: IVec3, chunk: &mut Structure) {
+        let Some(blocks) = self.deferred.remove(&position) else {
+            return;
+        };
+        let edited = blocks
+            .iter()
+            .map(|&(local_position, _)| local_position)
+            .collect::<Vec<_>>();
+        chunk.set_block(blocks);
+        refresh_surface(chunk, edited);
+    }
+}
+
+fn spawn(mut world: ResMut<VoxelWorld>, mut commands: Commands) {
+    let finished = world
+        .chunk_futures
+        .iter()
+        .filter(|(_, future)| future.is_finished())
+        .map(|(&position, _)| position)
+        .collect::<Vec<_>>();
+    for position in finished {
+        let chunk_future = world.chunk_futures.remove(&position).unwrap();
+        let mut chunk = tasks::block_on(async { chunk_future.await });
+        world.restore_edits(position, &mut chunk);
+
+        let heightmap = Heightmap::new(&chunk);
+        let entity = commands
+            .spawn((chunk, heightmap, Chunk(position), Dirty))
+            .id();
+        world.mapping.insert(position, entity);
+    }
+}
+
+const FACE_NEIGHBORS: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::X,
+    IVec3::NEG_Y,
+    IVec3::Y,
+    IVec3::NEG_Z,
+    IVec3::Z,
+];
+
+pub(crate) fn consolidate(bevy_world: &mut bevy::prelude::World) {
+    let mut surfaces = vec![];
+    let mut uniform_chunks = vec![];
+
+    let mut system_state = SystemState::<(
+        Res<VoxelWorld>,
+        Query<(Entity, &Chunk, &Structure), With<Dirty>>,
+        Query<&Structure>,
+    )>::new(bevy_world);
+    let (world, query, structures) = system_state.get(bevy_world);
+    for (entity, Chunk(position), structure) in query.iter() {
+        let solid = |offset: IVec3| {
+            world
+                .chunk_entity(*position + offset)
+                .and_then(|neighbor| structures.get(neighbor).ok())
+                .and_then(Structure::uniform_block)
+                .is_some_and(|block| block != Block::Air)
+        };
+        // A solid chunk only shows faces where a neighbor has air at their
+        // shared border, so its border is computed like any other chunk's.
+        match structure.uniform_block() {
+            Some(Block::Air) => {
+                uniform_chunks.push(entity);
+                continue;
+            }
+            Some(_) if FACE_NEIGHBORS.into_iter().all(solid) => {
+                uniform_chunks.push(entity);
+                continue;
+            }
+            _ => {}
+        }
+        if !all_neighbors_present(&world.mapping, *position) {
+            continue;
+        }
+        let neighborhood = Neighborhood::gather(|offset| {
+            world
+                .mapping
+                .get(&(*position + offset))
+                .and_then(|&neighbor| structures.get(neighbor).ok())
+        });
+        let index = border_indices(structure.size()).collect::<Vec<_>>();
+        let cull = cull_faces(&neighborhood, index.iter().copied());
+        let ao = ao_faces(&neighborhood, index.into_iter());
+        surfaces.push((entity, cull, ao));
+    }
+    drop(world);
+    drop(query);
+    drop(structures);
+    drop(system_state);
+
+    for entity in uniform_chunks {
+        bevy_world.entity_mut(entity).remove::<Dirty>();
+    }
+
+    for (chunk_entity, cull, ao) in surfaces {
+        let mut chunk = bevy_world.get_mut::<Structure>(chunk_entity).unwrap();
+        chunk.set_cull(cull);
+        chunk.set_ao(ao);
+        bevy_world.entity_mut(chunk_entity).remove::<Dirty>();
+    }
+}
+
+const FRUSTUM_PENALTY: f32 = 3.0;
+
+pub fn chunk_thread_count() -> usize {
+    std::thread::available_parallelism()
+        .map(|count| count.get().saturating_sub(1))
+        .unwrap_or(1)
+        .max(1)
+}
+
+fn max_chunks_in_flight() -> usize {
+    chunk_thread_count() * 2
+}
+
+fn load_priority(position: IVec3, focus: Vec3, frustum: Option<&Frustum>) -> f32 {
+    let min = position.as_vec3() * CHUNK_AXIS as f32;
+    let max = min + CHUNK_AXIS as f32;
+    let distance = ((min + max) / 2.0).distance(focus);
+    let aabb = Aabb::from_min_max(min, max);
+    match frustum {
+        Some(frustum) if !frustum.intersects_obb(&aabb, &Affine3A::IDENTITY, true, false) => {
+            distance * FRUSTUM_PENALTY
+        }
+        _ => distance,
+    }
+}
+
+fn load(
+    query1: Query<(&Camera, &Parent, &Frustum)>,
+    query2: Query<(&Transform)>,
+    terrain: Res<Terrain>,
+    mut world: ResMut<VoxelWorld>,
+) {
+    // Without a camera (headless runs) the world loads around the origin.
+    let (focus, frustum) = match query1.get_single() {
+        Ok((_, camera_parent, frustum)) => {
+            let camera_transform = query2.get(camera_parent.get()).unwrap();
+            (camera_transform.translation, Some(frustum))
+        }
+        Err(_) => (Vec3::ZERO, None),
+    };
+    let position = focus.as_ivec3() / CHUNK_AXIS as i32;
+    let world = &mut *world;
+
+    if position != world.origin {
+        world.origin = position;
+
+        let mut needed = HashSet::new();
+
+        let perlin = terrain_noise(terrain.seed);
+        let view = world.view as i32;
+        for x in -view..=view {
+            for z in -view..=view {
+                let column = world.origin.xz() + IVec2::new(x, z);
+                let (low, high) = column_range(world, &perlin, column);
+                for y in low..=high {
+                    needed.insert(IVec3::new(column.x, y, column.y));
+                }
+            }
+        }
+
+        let loaded = &mut world.loaded;
+        world.chunk_futures.retain(|position, _| {
+            let keep = needed.contains(position);
+            if !keep {
+                loaded.remove(position);
+            }
+            keep
+        });
+
+        world.pending = needed.difference(&world.loaded).copied().collect();
+    }
+
+    if world.pending.is_empty() {
+        return;
+    }
+
+    world.pending.sort_by_cached_key(|&position| {
+        Reverse(FloatOrd(load_priority(position, focus, frustum)))
+    });
+
+    let seed = terrain.seed;
+    let pool = AsyncComputeTaskPool::get_or_init(|| {
+        TaskPoolBuilder::new()
+            .num_threads(chunk_thread_count())
+            .build()
+    });
+    while world.chunk_futures.len() < max_chunks_in_flight() {
+        let Some(position) = world.pending.pop() else {
+            break;
+        };
+        let chunk_future = pool.spawn(async move {
+            let mut chunk = gen_chunk(seed, position);
+            if chunk.uniform_block().is_none() {
+                let index = 0..chunk.count() as u64;
+                calc_ao(&mut chunk, index.clone());
+                calc_cull(&mut chunk, index);
+            }
+            chunk
+        });
+
+        world.chunk_futures.insert(position, chunk_future);
+        world.loaded.insert(position);
+    }
+}
+
+fn all_neighbors_present(chunk_mappings: &HashMap<IVec3, Entity>, position: IVec3) -> bool {
+    let mut neighbors = 0;
+    all_neighbors(position, |neighbor| {
+        if chunk_mappings.contains_key(&neighbor) {
+            neighbors += 1;
+        }
+    });
+    neighbors == 3usize.pow(3)
+}
+
+pub fn set_block(bevy_world: &mut bevy::prelude::World, position: IVec3, block: Block) {
+    iter::once((position, block))
+        .collect::<WorldEdit>()
+        .apply(bevy_world);
+}
+
+/// Block writes gathered per chunk and applied together, so that every chunk
+/// is written and marked dirty once however many of its voxels change.
+/// Later writes to the same position win.
+#[derive(Default)]
+pub struct WorldEdit {
+    chunks: HashMap<IVec3, Vec<(UVec3, Block)>>,
+}
+
+impl WorldEdit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, position: IVec3, block: Block) -> &mut Self {
+        let chunk_position = position.div_euclid(IVec3::splat(CHUNK_AXIS as i32));
+        let local_position = position
+            .rem_euclid(IVec3::splat(CHUNK_AXIS as i32))
+            .as_uvec3();
+        self.chunks
+            .entry(chunk_position)
+            .or_default()
+            .push((local_position, block));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Writes every chunk with a single `Structure::set_block` and rescans
+    /// the heightmap columns that changed. Faces and ambient occlusion are
+    /// then recomputed once per chunk for the changed voxels and everything
+    /// around them, across chunk borders, and those chunks are marked dirty.
+    /// Writes to chunks that are not loaded yet are queued until they are
+    /// generated; returns how many chunks that applies to.
+    pub fn apply(self, bevy_world: &mut bevy::prelude::World) -> usize {
+        let axis = IVec3::splat(CHUNK_AXIS as i32);
+        let mut heightmaps = bevy_world.query::<(&Structure, &mut Heightmap)>();
+        let mut surfaces = HashMap::<IVec3, Vec<UVec3>>::new();
+        let mut deferred = 0;
+        for (chunk_position, blocks) in self.chunks {
+            let mut world = bevy_world.resource_mut::<VoxelWorld>();
+            let Some(chunk_entity) = world.chunk_entity(chunk_position) else {
+                world
+                    .deferred
+                    .entry(chunk_position)
+                    .or_default()
+                    .extend(blocks);
+                deferred += 1;
+                continue;
+            };
+            for &(local_position, _) in &blocks {
+                let position = chunk_position * axis + local_position.as_ivec3();
+                all_neighbors(position, |neighbor| {
+                    surfaces
+                        .entry(neighbor.div_euclid(axis))
+                        .or_default()
+                        .push(neighbor.rem_euclid(axis).as_uvec3());
+                });
+            }
+
+            let columns = blocks
+                .iter()
+                .map(|(local_position, _)| local_position.xz())
+                .collect::<HashSet<_>>();
+            bevy_world
+                .get_mut::<Structure>(chunk_entity)
+                .unwrap()
+                .set_block(blocks);
+            if let Ok((chunk, mut heightmap)) = heightmaps.get_mut(bevy_world, chunk_entity) {
+                for column in columns {
+                    heightmap.update(chunk, column);
+                }
+            }
+        }
+
+        // Faces are computed once every chunk holds its new blocks, so that
+        // voxels on a border see the writes on the other side of it.
+        let mut updates = vec![];
+        let mut system_state = SystemState::<(Res<VoxelWorld>, Query<&Structure>)>::new(bevy_world);
+        let (world, structures) = system_state.get(bevy_world);
+        for (chunk_position, voxels) in surfaces {
+            let Some(chunk_entity) = world.chunk_entity(chunk_position) else {
+                continue;
+            };
+            let neighborhood = Neighborhood::gather(|offset| {
+                world
+                    .chunk_entity(chunk_position + offset)
+                    .and_then(|neighbor| structures.get(neighbor).ok())
+            });
+            let structure = neighborhood.center();
+            let mut index = voxels
+                .into_iter()
+                .map(|voxel| structure.linearize(voxel) as u64)
+                .collect::<Vec<_>>();
+            index.sort_unstable();
+            index.dedup();
+            let cull = cull_faces(&neighborhood, index.iter().copied());
+            let ao = ao_faces(&neighborhood, index.into_iter());
+            updates.push((chunk_entity, cull, ao));
+        }
+        drop(world);
+        drop(structures);
+        drop(system_state);
+
+        for (chunk_entity, cull, ao) in updates {
+            let mut chunk = bevy_world.get_mut::<Structure>(chunk_entity).unwrap();
+            chunk.set_cull(cull);
+            chunk.set_ao(ao);
+            bevy_world.entity_mut(chunk_entity).remove::<Active>();
+            bevy_world.entity_mut(chunk_entity).insert(Dirty);
+        }
+        deferred
+    }
+}
+
+impl Extend<(IVec3, Block)> for WorldEdit {
+    fn extend<T: IntoIterator<Item = (IVec3, Block)>>(&mut self, edits: T) {
+        for (position, block) in edits {
+            self.set(position, block);
+        }
+    }
+}
+
+impl FromIterator<(IVec3, Block)> for WorldEdit {
+    fn from_iter<T: IntoIterator<Item = (IVec3, Block)>>(edits: T) -> Self {
+        let mut edit = WorldEdit::new();
+        edit.extend(edits);
+        edit
+    }
+}
+
+pub fn get_block(bevy_world: &mut bevy::prelude::World, position: IVec3) -> Option<Block> {
+    let chunk_position = position.div_euclid(IVec3::splat(CHUNK_AXIS as i32));
+
+    if let Some(&chunk_entity) = bevy_world.resource::<VoxelWorld>().mapping.get(&chunk_position) {
+        let mut chunk = bevy_world.get_mut::<Structure>(chunk_entity).unwrap();
+
+        let local_position = position
+            .rem_euclid(IVec3::splat(CHUNK_AXIS as i32))
+            .as_uvec3();
+
+        return chunk.get_block(iter::once(local_position)).next();
+    }
+    None
+}
+
+/// Height of the first air voxel at or above `position` in its column.
+pub fn get_ground_level(
+    bevy_world: &mut bevy::prelude::World,
+    mut position: IVec3,
+) -> Result<i32, ColumnError> {
+    loop {
+        match get_block(bevy_world, position) {
+            Some(Block::Air) => return Ok(position.y),
+            Some(_) => position.y += 1,
+            None => return Err(ColumnError::Unloaded(position)),
+        }
+    }
+}